@@ -1,2 +1,6 @@
 #!/bin/bash
-customasm ./asm/phinixplus.asm ./asm/main.asm --format logisim16 -o ./program.hex
\ No newline at end of file
+# usage: ./build_prog.sh [format] [output]
+# format is any customasm output format the emulator loads: logisim16, binary, intelhex, hexstr, binstr
+FORMAT=${1:-logisim16}
+OUT=${2:-./program.hex}
+customasm ./asm/phinixplus.asm ./asm/main.asm --format "$FORMAT" -o "$OUT"
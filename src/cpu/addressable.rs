@@ -1,12 +1,50 @@
-use std::path::Path;
+use std::{io::{self, Read}, path::Path};
 
-use crate::MAGIC_NUMBER;
+use crate::{loader::{self, LoadError, LoadMode}, MAGIC_NUMBER};
 
+/// Memory or a device answering a range of addresses, which it sees starting from 0
+/// - `read` takes `&mut self` because device reads may have side effects, such as dequeuing input
 pub trait Addressable {
-    fn read(&self, loc: i32) -> i32;
+    fn read(&mut self, loc: i32) -> i32;
     fn write(&mut self, loc: i32, val: i32);
 
-    fn load_file(&mut self, file: &Path) -> i32;
+    /// Number of addresses decoded
+    fn size(&self) -> usize;
+
+    /// Read without side effects, for debuggers and dumps
+    /// - None when the value can't be known without disturbing the device
+    fn peek(&self, _loc: i32) -> Option<i32> {
+        None
+    }
+
+    /// Write anything buffered out to backing storage, a no-op for volatile memory and devices
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Something program images can be loaded into
+pub trait Loadable: Addressable {
+    /// Copy decoded words in from `base` with ordinary writes, returns the last address written
+    fn load_words_at(&mut self, base: u16, words: &[i32]) -> i32 {
+        for (pos, val) in words.iter().enumerate() {
+            self.write(base as i32 + pos as i32, *val);
+        }
+        base as i32 + words.len() as i32 - 1
+    }
+
+    /// Copy decoded words in from address 0, returns the last address written
+    fn load_words(&mut self, words: &[i32]) -> i32 {
+        self.load_words_at(0, words)
+    }
+
+    fn load_file(&mut self, file: &Path) -> Result<i32, LoadError> {
+        Ok(self.load_words(&loader::load_file(file, None, LoadMode::Lenient)?))
+    }
+
+    fn load_reader(&mut self, reader: &mut dyn Read) -> Result<i32, LoadError> {
+        Ok(self.load_words(&loader::load_reader(reader, None, None, LoadMode::Lenient)?))
+    }
 }
 
 pub struct Memory {
@@ -14,43 +52,30 @@ pub struct Memory {
 }
 
 impl Addressable for Memory {
-    fn load_file(&mut self, file: &Path) -> i32 {
-        let content = std::fs::read_to_string(file).unwrap();
-        let mut lines = content.lines().collect::<Vec<&str>>();
-        if lines[0] != "v2.0 raw" {
-            panic!("[ERR] File is of invalid format.");
-        }
-        lines.remove(0);
-        let words = lines
-            .iter()
-            .map(|elem| format!("{} ", elem))
-            .collect::<Vec<String>>()
-            .concat()
-            .split_whitespace()
-            .map(|e| {
-                i32::from_str_radix(e, 16).unwrap_or_else(|_| {
-                    println!("[WARN] Invalid Hex: {}, replacing with 0", e);
-                    0
-                })
-            })
-            .collect::<Vec<i32>>();
+    fn read(&mut self, loc: i32) -> i32 {
+        self.peek(loc).unwrap_or(0)
+    }
 
-        for (pos, val) in words.iter().enumerate() {
-            if val & MAGIC_NUMBER != *val {
-                println!("[WARN] Value {} at location {} out of range, trimming to 16 bits.", val, pos);
-            }
-            self.write(pos as i32, val & MAGIC_NUMBER)
+    fn write(&mut self, loc: i32, val: i32) {
+        if let Some(word) = self.memory.get_mut((loc & MAGIC_NUMBER) as usize) {
+            *word = val;
         }
+    }
 
-        return (words.len() - 1) as i32;
+    fn size(&self) -> usize {
+        self.memory.len()
     }
 
-    fn read(&self, loc: i32) -> i32 {
-        self.memory[(loc & MAGIC_NUMBER) as usize]
+    fn peek(&self, loc: i32) -> Option<i32> {
+        Some(self.memory.get((loc & MAGIC_NUMBER) as usize).copied().unwrap_or(0))
     }
+}
 
-    fn write(&mut self, loc: i32, val: i32) {
-        self.memory[(loc & MAGIC_NUMBER) as usize] = val;
+impl Loadable for Memory {}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
     }
 }
 
@@ -58,4 +83,9 @@ impl Memory {
     pub fn new() -> Memory {
         Memory { memory: vec![0; 65536] }
     }
+
+    /// RAM of `words` words for mapping on a bus, accesses past the end read 0 and are dropped
+    pub fn with_size(words: usize) -> Memory {
+        Memory { memory: vec![0; words] }
+    }
 }
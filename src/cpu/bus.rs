@@ -0,0 +1,179 @@
+use std::io;
+
+use crate::{route::RouteTable, MAGIC_NUMBER};
+
+use super::addressable::{Addressable, Loadable, Memory};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What a write to a read-only address does, the write itself is always dropped
+pub enum RomWritePolicy {
+    /// drop it silently like real ROM
+    Ignore,
+    /// print a warning and keep running
+    Log,
+    /// stop the CPU with `StopReason::WriteFault`
+    Fault,
+}
+
+/// A backend mapped into the address space, it sees addresses relative to `start`
+struct Region {
+    name: String,
+    start: i32,
+    len: i32,
+    backend: Box<dyn Addressable + Send>,
+}
+
+/// Data address space routing address ranges to RAM, ROM or device backends
+/// - later mappings shadow earlier ones where they overlap
+/// - reads from unmapped addresses return 0 and writes to them are dropped
+/// - any address can be marked read-only, independent of what is mapped there
+pub struct Bus {
+    regions: Vec<Region>,
+    routes: RouteTable,
+    read_only: Vec<bool>,
+    rom_policy: RomWritePolicy,
+    violation: Option<(u16, i32)>,
+}
+
+impl Bus {
+    /// Empty address space with nothing mapped
+    pub fn new() -> Bus {
+        Bus {
+            regions: Vec::new(),
+            routes: RouteTable::new(65536),
+            read_only: vec![false; 65536],
+            rom_policy: RomWritePolicy::Log,
+            violation: None,
+        }
+    }
+
+    /// Address space backed entirely by one 64K RAM, the classic memory layout
+    pub fn with_ram() -> Bus {
+        let mut bus = Bus::new();
+        bus.map("ram", 0, 65536, Box::new(Memory::new()));
+        bus
+    }
+
+    /// Map `backend` at `start` for `len` words, clipped to the end of the address space
+    pub fn map(&mut self, name: &str, start: u16, len: usize, backend: Box<dyn Addressable + Send>) {
+        let len = len.min(65536 - start as usize) as i32;
+        self.regions.push(Region { name: name.to_string(), start: start as i32, len, backend });
+        self.rebuild();
+    }
+
+    /// Remove the most recent mapping called `name`, uncovering whatever it shadowed
+    pub fn unmap(&mut self, name: &str) -> Option<Box<dyn Addressable + Send>> {
+        let idx = self.regions.iter().rposition(|r| r.name == name)?;
+        let region = self.regions.remove(idx);
+        self.rebuild();
+        Some(region.backend)
+    }
+
+    /// Name, start address and length of every mapping in the order they were made
+    pub fn regions(&self) -> impl Iterator<Item = (&str, u16, usize)> {
+        self.regions.iter().map(|r| (r.name.as_str(), r.start as u16, r.len as usize))
+    }
+
+    /// Write straight to the backend even if the address is read-only
+    pub fn poke(&mut self, loc: i32, val: i32) {
+        if let Some((idx, offset)) = self.route(loc) {
+            self.regions[idx].backend.write(offset, val);
+        }
+    }
+
+    /// Mark `len` words from `start` read-only, clipped to the end of the address space
+    pub fn protect(&mut self, start: u16, len: usize) {
+        let end = (start as usize + len).min(65536);
+        self.read_only[start as usize..end].fill(true);
+    }
+
+    /// Make `len` words from `start` writable again
+    pub fn unprotect(&mut self, start: u16, len: usize) {
+        let end = (start as usize + len).min(65536);
+        self.read_only[start as usize..end].fill(false);
+    }
+
+    pub fn is_read_only(&self, loc: i32) -> bool {
+        self.read_only[(loc & MAGIC_NUMBER) as usize]
+    }
+
+    pub fn rom_policy(&self) -> RomWritePolicy {
+        self.rom_policy
+    }
+
+    pub fn set_rom_policy(&mut self, policy: RomWritePolicy) {
+        self.rom_policy = policy;
+    }
+
+    /// Address and value of the last read-only write that was not silently ignored
+    pub fn take_violation(&mut self) -> Option<(u16, i32)> {
+        self.violation.take()
+    }
+
+    fn rebuild(&mut self) {
+        self.routes.rebuild(self.regions.iter().map(|r| (r.start as usize, r.len as usize)));
+    }
+
+    fn route(&self, loc: i32) -> Option<(usize, i32)> {
+        self.routes.route((loc & MAGIC_NUMBER) as usize).map(|(idx, offset)| (idx, offset as i32))
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Bus {
+        Bus::new()
+    }
+}
+
+/// Loading ignores write protection, so ROM images can be placed after protecting their range
+impl Loadable for Bus {
+    fn load_words_at(&mut self, base: u16, words: &[i32]) -> i32 {
+        for (pos, val) in words.iter().enumerate() {
+            self.poke(base as i32 + pos as i32, *val);
+        }
+        base as i32 + words.len() as i32 - 1
+    }
+}
+
+impl Addressable for Bus {
+    /// Flush every mapping, including shadowed ones, reporting the first error
+    fn flush(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for region in &mut self.regions {
+            let flushed = region.backend.flush();
+            if result.is_ok() {
+                result = flushed;
+            }
+        }
+        result
+    }
+
+    fn read(&mut self, loc: i32) -> i32 {
+        match self.route(loc) {
+            Some((idx, offset)) => self.regions[idx].backend.read(offset),
+            None => 0,
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.routes.size()
+    }
+
+    /// Unmapped addresses peek as 0 like they read
+    fn peek(&self, loc: i32) -> Option<i32> {
+        match self.route(loc) {
+            Some((idx, offset)) => self.regions[idx].backend.peek(offset),
+            None => Some(0),
+        }
+    }
+
+    fn write(&mut self, loc: i32, val: i32) {
+        if self.is_read_only(loc) {
+            if self.rom_policy != RomWritePolicy::Ignore {
+                self.violation = Some(((loc & MAGIC_NUMBER) as u16, val));
+            }
+            return;
+        }
+        self.poke(loc, val);
+    }
+}
@@ -1,11 +1,50 @@
-use std::path::Path;
+use std::{fmt, io, path::Path, time::{Duration, Instant}};
 
-use crate::io::IO;
+use crate::{diff::{self, DiffRange}, export::{self, DumpFormat}, io::{IO, watchdog::WatchdogAction}, loader::{self, ImageFormat, ImageSpec, LoadError, LoadMode}, sourcemap::SourceMap, symbols::SymbolTable, MAGIC_NUMBER};
 
-use super::addressable::{Addressable, Memory};
+use super::{addressable::{Addressable, Loadable}, bus::{Bus, RomWritePolicy}, init::InitPattern, watch::{WatchKind, Watchpoints}};
 
 const DOUBLE_WORD: [u32; 8] = [0x00004000, 0x00000000, 0xAAAAC00C, 0xA0000000, 0x00000000, 0x00000000, 0xFFFF0000, 0x0000F0F0];
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Why emulation stopped
+pub enum StopReason {
+    /// the guest set the halt bit in `st`
+    Halted,
+    /// the host's instruction budget ran out
+    InstructionLimit,
+    /// the watchdog expired while configured to stop
+    Watchdog,
+    /// the instruction at `ip` wrote to read-only `addr` while ROM writes fault
+    WriteFault { addr: u16, ip: u16 },
+    /// the instruction `instr` at `ip` made a `kind` access of `value` at `addr` matching a watchpoint
+    Watchpoint { kind: WatchKind, addr: u16, value: u16, ip: u16, instr: u16 },
+    /// the user pressed the terminal backend's escape key
+    HostEscape,
+    /// the word `instr` fetched from `ip` is not an instruction
+    InvalidInstruction { ip: u16, instr: u16 },
+}
+
+impl StopReason {
+    /// Human-readable reason with addresses formatted by `fmt_addr`, usually `CPU::describe_addr`
+    pub fn describe(&self, fmt_addr: impl Fn(u16) -> String) -> String {
+        match self {
+            StopReason::Halted => "halted".to_string(),
+            StopReason::InstructionLimit => "instruction limit reached".to_string(),
+            StopReason::Watchdog => "watchdog expired".to_string(),
+            StopReason::WriteFault { addr, ip } => format!("write to read-only {} by instruction at {}", fmt_addr(*addr), fmt_addr(*ip)),
+            StopReason::HostEscape => "escape key pressed on the terminal".to_string(),
+            StopReason::InvalidInstruction { ip, instr } => format!("invalid instruction {:04x} at {}", instr, fmt_addr(*ip)),
+            StopReason::Watchpoint { kind, addr, value, ip, instr } => format!("{} watchpoint on {} hit with {:#06x} by instruction {:04x} at {}", kind, fmt_addr(*addr), value, instr, fmt_addr(*ip)),
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.describe(|addr| format!("{:#06x}", addr)))
+    }
+}
 
 pub struct CPU {
     primary_regfile: Vec<i32>,
@@ -15,12 +54,32 @@ pub struct CPU {
     reg_rf: i32,
     pub reg_st: i32,
     skip: bool,
-    mem: Memory,
+    entry: i32,
+    reg_init: InitPattern,
+    stop: Option<StopReason>,
+    pub mem: Bus,
     pub io_space: IO,
+    pub symbols: SymbolTable,
+    pub source_map: SourceMap,
+    pub watchpoints: Watchpoints,
+    flush_interval: Option<Duration>,
+    last_flush: Instant,
+    flush_check: u32,
+}
+
+impl Default for CPU {
+    fn default() -> CPU {
+        CPU::new()
+    }
 }
 
 impl CPU {
     pub fn new() -> CPU {
+        CPU::with_io(IO::init())
+    }
+
+    /// CPU using an already configured I/O space, such as one from `IO::with_serial`
+    pub fn with_io(io_space: IO) -> CPU {
         CPU {
             primary_regfile: vec![0; 16],
             secondary_regfile: vec![0; 16],
@@ -29,33 +88,231 @@ impl CPU {
             reg_rf: 0,
             reg_st: 0,
             skip: false,
-            mem: Memory::new(),
-            io_space: IO::init(),
+            entry: 0,
+            reg_init: InitPattern::Zero,
+            stop: None,
+            mem: Bus::with_ram(),
+            io_space,
+            symbols: SymbolTable::new(),
+            source_map: SourceMap::new(),
+            watchpoints: Watchpoints::new(),
+            flush_interval: None,
+            last_flush: Instant::now(),
+            flush_check: 0,
         }
     }
 
-    pub fn load_prog(&mut self) {
-        self.mem.load_file(Path::new("program.hex"));
+    pub fn load_prog(&mut self) -> Result<(), LoadError> {
+        self.mem.load_file(Path::new("program.hex")).map(|_| ())
+    }
+
+    /// Load an image from address 0, detecting its format unless one is given
+    pub fn load_prog_file(&mut self, file: &Path, format: Option<ImageFormat>, mode: LoadMode) -> Result<(), LoadError> {
+        self.load_images(&[ImageSpec { format, ..ImageSpec::new(file, 0) }], mode)
+    }
+
+    /// Load words given inline from address 0, e.g. a tiny program embedded in a test
+    pub fn load_prog_words(&mut self, words: &[u16]) {
+        let words = words.iter().take(loader::MAX_WORDS).map(|w| *w as i32).collect::<Vec<i32>>();
+        self.mem.load_words_at(0, &words);
+    }
+
+    /// Load an image read from `reader` until it ends from address 0, detecting its format unless one is given
+    pub fn load_prog_reader(&mut self, reader: &mut impl io::Read, format: Option<ImageFormat>, mode: LoadMode) -> Result<(), LoadError> {
+        let words = loader::load_reader(reader, None, format, mode)?;
+        self.mem.load_words_at(0, &words);
+        Ok(())
+    }
+
+    /// Load an image piped in on stdin from address 0, e.g. straight from the assembler
+    pub fn load_prog_stdin(&mut self, format: Option<ImageFormat>, mode: LoadMode) -> Result<(), LoadError> {
+        self.load_prog_file(Path::new("-"), format, mode)
+    }
+
+    /// Load several images at their own base addresses
+    /// - nothing is written unless every image reads cleanly and none overlap
+    /// - read-only images are protected once written
+    pub fn load_images(&mut self, images: &[ImageSpec], mode: LoadMode) -> Result<(), LoadError> {
+        for ((base, words), spec) in loader::load_images(images, mode)?.into_iter().zip(images) {
+            self.mem.load_words_at(base, &words);
+            if spec.read_only {
+                self.mem.protect(base, words.len());
+            }
+        }
+        Ok(())
+    }
+
+    /// Set where execution starts, now and after every reset
+    pub fn set_entry(&mut self, ip: u16) {
+        self.entry = ip as i32;
+        self.reg_ip = self.entry;
+    }
+
+    /// Write `len` words of memory from `start` to `out`, clipped to the end of the address space
+    pub fn dump_memory(&self, out: &mut impl io::Write, format: DumpFormat, start: u16, len: usize) -> io::Result<()> {
+        let end = (start as usize + len).min(65536);
+        let words = (start as usize..end).map(|addr| self.peek(addr as u16)).collect::<Vec<i32>>();
+        export::write_dump(out, format, start, &words, &self.symbols)
+    }
+
+    /// Memory word at `addr` read without side effects, devices that can't be peeked show as 0
+    pub fn peek(&self, addr: u16) -> i32 {
+        self.mem.peek(addr as i32).unwrap_or(0)
+    }
+
+    /// Copy of the whole address space, to compare against later with `diff_since`
+    pub fn snapshot(&self) -> Vec<i32> {
+        (0..=u16::MAX).map(|addr| self.peek(addr)).collect()
+    }
+
+    /// Words that changed since `snapshot` was taken, grouped into ranges
+    pub fn diff_since(&self, snapshot: &[i32]) -> Vec<DiffRange> {
+        diff::diff_words(0, snapshot, &self.snapshot())
+    }
+
+    /// Write persistent memory such as NVRAM back to its files
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        self.mem.flush()
+    }
+
+    /// Also flush every `interval` while running, None only flushes on stop and when asked
+    pub fn set_flush_interval(&mut self, interval: Option<Duration>) {
+        self.flush_interval = interval;
+    }
+
+    /// Fill the whole address space with `pattern`, ignoring write protection
+    /// - meant for before images are loaded and persistent memory is mapped, since it overwrites both
+    pub fn fill_memory(&mut self, pattern: InitPattern) {
+        for (addr, val) in (0..65536).zip(pattern.words()) {
+            self.mem.poke(addr, val);
+        }
+    }
+
+    /// Set what the general, `jp` and `rf` registers hold at power-on, applied now and on every reset
+    pub fn set_register_init(&mut self, pattern: InitPattern) {
+        self.reg_init = pattern;
+        self.reset();
+    }
+
+    /// Reset registers and flags to their power-on state, memory is left untouched
+    /// - `st` always starts clear so the CPU isn't halted before it begins
+    pub fn reset(&mut self) {
+        let mut words = self.reg_init.register_words();
+        for reg in self.primary_regfile.iter_mut().chain(self.secondary_regfile.iter_mut()) {
+            *reg = words.next().unwrap_or(0);
+        }
+        self.primary_regfile[0] = 0;
+        self.reg_ip = self.entry;
+        self.reg_jp = words.next().unwrap_or(0);
+        self.reg_rf = words.next().unwrap_or(0);
+        self.reg_st = 0;
+        self.skip = false;
+        self.stop = None;
+        self.io_space.watchdog().kick();
+    }
+
+    /// Address of the next instruction
+    pub fn ip(&self) -> u16 {
+        (self.reg_ip & MAGIC_NUMBER) as u16
+    }
+
+    /// `label+offset (0xADDR)` followed by the source line the address was assembled from, when known
+    pub fn describe_addr(&self, addr: u16) -> String {
+        match self.source_map.lookup(addr) {
+            Some(location) => format!("{} [{}]", self.symbols.describe(addr), location),
+            None => self.symbols.describe(addr),
+        }
+    }
+
+    /// Some(reason) once the CPU should not execute any further
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        if is_set(self.reg_st, 0) {
+            return Some(StopReason::Halted);
+        }
+        self.stop
     }
 
     fn get_imx(&mut self) -> i32 {
         let ip = self.reg_ip;
         self.reg_ip += 1;
+        self.io_space.perf.cycles.bump();
         self.mem.read(ip)
     }
 
+    /// data memory read, counted by the performance counters and checked against watchpoints
+    fn load(&mut self, loc: i32) -> i32 {
+        self.io_space.perf.cycles.bump();
+        self.io_space.perf.mem_accesses.bump();
+        let val = self.mem.read(loc);
+        if !self.watchpoints.is_empty() {
+            self.watchpoints.check(WatchKind::Read, (loc & MAGIC_NUMBER) as u16, (val & MAGIC_NUMBER) as u16);
+        }
+        val
+    }
+
+    /// data memory write, counted by the performance counters and checked against watchpoints
+    fn store(&mut self, loc: i32, val: i32) {
+        self.io_space.perf.cycles.bump();
+        self.io_space.perf.mem_accesses.bump();
+        if !self.watchpoints.is_empty() {
+            self.watchpoints.check(WatchKind::Write, (loc & MAGIC_NUMBER) as u16, (val & MAGIC_NUMBER) as u16);
+        }
+        self.mem.write(loc, val);
+    }
+
     pub fn exec(&mut self) {
+        let ip = self.ip();
         let instr_word = self.mem.read(self.reg_ip);
         self.reg_ip += 1;
+        self.io_space.perf.cycles.bump();
         let opcode = get_opc(instr_word);
         if self.skip {
             if (DOUBLE_WORD[(opcode >> 5) as usize] & (1 << (opcode & 31))) != 0 {
                 self.reg_ip += 1;
             } 
             self.skip = false;
+            self.io_space.perf.skipped.bump();
         } else {
-            self.exec_opcode(opcode, instr_word);
+            self.exec_opcode(opcode, instr_word, ip);
             self.primary_regfile[0] = 0;
+            self.io_space.perf.retired.bump();
+        }
+        if let Some((addr, val)) = self.mem.take_violation() {
+            match self.mem.rom_policy() {
+                RomWritePolicy::Fault => self.stop = Some(StopReason::WriteFault { addr, ip }),
+                _ => println!("[WARN] Instruction at {} wrote {:#06x} to read-only {}, ignored", self.describe_addr(ip), val, self.describe_addr(addr)),
+            }
+        }
+        if let Some(access) = self.io_space.ports.take_unmapped() {
+            match access.value {
+                Some(val) => println!("[WARN] Instruction at {} wrote {:#06x} to unmapped port {:#04x}", self.describe_addr(ip), val, access.port),
+                None => println!("[WARN] Instruction at {} read unmapped port {:#04x}", self.describe_addr(ip), access.port),
+            }
+        }
+        if let Some(hit) = self.watchpoints.take_hit() {
+            self.stop = Some(StopReason::Watchpoint { kind: hit.kind, addr: hit.addr, value: hit.value, ip, instr: (instr_word & MAGIC_NUMBER) as u16 });
+        }
+        if self.io_space.escape_requested() {
+            self.stop = Some(StopReason::HostEscape);
+        }
+        match self.io_space.watchdog().tick() {
+            // a reset clears `stop`, so it would swallow a watchpoint hit or fault from this same instruction
+            Some(WatchdogAction::Reset) if self.stop.is_some() => (),
+            Some(WatchdogAction::Reset) => {
+                println!("[WARN] Watchdog expired at {}, resetting CPU", self.describe_addr(self.ip()));
+                self.reset();
+            }
+            Some(WatchdogAction::Stop) => self.stop = Some(StopReason::Watchdog),
+            None => (),
+        }
+        // reading the clock every instruction is too slow, look at it every 4096
+        self.flush_check = self.flush_check.wrapping_add(1);
+        let due = self.flush_interval.is_some_and(|interval| self.flush_check.is_multiple_of(4096) && self.last_flush.elapsed() >= interval);
+        if due || self.stop_reason().is_some() {
+            if let Err(err) = self.flush() {
+                println!("[WARN] Flushing persistent memory failed: {}", err);
+            }
         }
     }
     
@@ -70,32 +327,33 @@ impl CPU {
 
     fn eval_cond(&self, opcode: i32) -> bool {
         match opcode & 7 {
-            0 => return is_set(self.reg_st, 13),
-            1 => return is_set(self.reg_st, 14),
-            2 => return is_set(self.reg_st, 12),
-            3 => return !is_set(self.reg_st, 12),
-            4 => return !is_set(self.reg_st, 13),
-            5 => return !is_set(self.reg_st, 13) | is_set(self.reg_st, 12),
-            6 => return (is_set(self.reg_st, 15) != is_set(self.reg_st, 14)) || is_set(self.reg_st, 12),
-            _ => return false
+            0 => is_set(self.reg_st, 13),
+            1 => is_set(self.reg_st, 14),
+            2 => is_set(self.reg_st, 12),
+            3 => !is_set(self.reg_st, 12),
+            4 => !is_set(self.reg_st, 13),
+            5 => !is_set(self.reg_st, 13) | is_set(self.reg_st, 12),
+            6 => (is_set(self.reg_st, 15) != is_set(self.reg_st, 14)) || is_set(self.reg_st, 12),
+            _ => false
         }
     }
     
     fn eval_prop(&self, opcode: i32, regval: i32) -> bool {
         match opcode & 7 {
-            0 => return regval == 0,
-            1 => return regval == self.reg_rf,
-            2 => return (regval&32768) != 0,
-            3 => return (regval&1) != 0,
-            4 => return regval != 0,
-            5 => return regval != self.reg_rf,
-            6 => return (regval&32768) == 0,
-            7 => return (regval&1) == 0,
-            _ => return false
+            0 => regval == 0,
+            1 => regval == self.reg_rf,
+            2 => (regval&32768) != 0,
+            3 => (regval&1) != 0,
+            4 => regval != 0,
+            5 => regval != self.reg_rf,
+            6 => (regval&32768) == 0,
+            7 => (regval&1) == 0,
+            _ => false
         }
     }
 
-    fn exec_opcode(&mut self, opcode: i32, iw: i32) {
+    /// `ip` is where `iw` was fetched from, for reporting faults
+    fn exec_opcode(&mut self, opcode: i32, iw: i32, ip: u16) {
         //println!("[INFO] executing instruction {}", iw);
         match opcode {
             0 => self.reg_st ^= 1 << get_ims(iw), // sig
@@ -489,15 +747,15 @@ impl CPU {
             }
             122 => self.reg_rf &= !if is_set(self.reg_rf, get_src(iw) as i32) { 0 } else { 1 << get_dst(iw) as i32 }, // rbc
             123 => self.reg_rf |= !if is_set(self.reg_rf, get_src(iw) as i32) { 1 << get_dst(iw) as i32 } else { 0 }, // rbd
-            124 => self.primary_regfile[get_dst(iw)] = self.mem.read(self.primary_regfile[get_src(iw)]), // ldrx
+            124 => self.primary_regfile[get_dst(iw)] = self.load(self.primary_regfile[get_src(iw)]), // ldrx
             125 => { // ldix
                 let val = self.get_imx();
-                self.primary_regfile[get_dst(iw)] = self.mem.read(self.primary_regfile[get_src(iw)] + val); 
+                self.primary_regfile[get_dst(iw)] = self.load(self.primary_regfile[get_src(iw)] + val); 
             },
-            126 => self.mem.write(self.primary_regfile[get_src(iw)], self.primary_regfile[get_dst(iw)]), // strx
+            126 => self.store(self.primary_regfile[get_src(iw)], self.primary_regfile[get_dst(iw)]), // strx
             127 => { // stix
                 let val = self.get_imx();
-                self.mem.write(self.primary_regfile[get_src(iw)] + val, self.primary_regfile[get_dst(iw)]);
+                self.store(self.primary_regfile[get_src(iw)] + val, self.primary_regfile[get_dst(iw)]);
             }
             
             128..=143 => self.primary_regfile[get_dst(iw)] = sxt8(get_imh(iw)), // lsi
@@ -522,58 +780,74 @@ impl CPU {
                     self.reg_ip = self.primary_regfile[get_src(iw)] + imm;
                 }
             }
-            224 => self.primary_regfile[get_dst(iw)] = self.mem.read(self.secondary_regfile[get_src(iw)]), // ldry
-            225 => self.primary_regfile[get_dst(iw)] = self.mem.read({ self.secondary_regfile[get_src(iw)] -= 1; self.secondary_regfile[get_src(iw)] }), // mldry
-            226 => self.primary_regfile[get_dst(iw)] = self.mem.read({ self.secondary_regfile[get_src(iw)] += 1; self.secondary_regfile[get_src(iw)]-1 }), // ldryp
-            227 => self.primary_regfile[get_dst(iw)] = self.mem.read({ self.secondary_regfile[get_src(iw)] += 1; self.secondary_regfile[get_src(iw)]}), // pldry
+            224 => self.primary_regfile[get_dst(iw)] = self.load(self.secondary_regfile[get_src(iw)]), // ldry
+            225 => { // mldry
+                self.secondary_regfile[get_src(iw)] -= 1;
+                self.primary_regfile[get_dst(iw)] = self.load(self.secondary_regfile[get_src(iw)]);
+            }
+            226 => { // ldryp
+                self.secondary_regfile[get_src(iw)] += 1;
+                self.primary_regfile[get_dst(iw)] = self.load(self.secondary_regfile[get_src(iw)]-1);
+            }
+            227 => { // pldry
+                self.secondary_regfile[get_src(iw)] += 1;
+                self.primary_regfile[get_dst(iw)] = self.load(self.secondary_regfile[get_src(iw)]);
+            }
             228 => { // ldiy
                 let imm = self.get_imx();
-                self.primary_regfile[get_dst(iw)] = self.mem.read(self.secondary_regfile[get_src(iw)] + imm);
+                self.primary_regfile[get_dst(iw)] = self.load(self.secondary_regfile[get_src(iw)] + imm);
             }
             229 => { // mldiy
                 let imm = self.get_imx();
-                self.primary_regfile[get_dst(iw)] = self.mem.read({ self.secondary_regfile[get_src(iw)] -= 1; self.secondary_regfile[get_src(iw)] } + imm);
+                self.secondary_regfile[get_src(iw)] -= 1;
+                self.primary_regfile[get_dst(iw)] = self.load(self.secondary_regfile[get_src(iw)] + imm);
             }
             230 => { // ldiyp
                 let imm = self.get_imx();
-                self.primary_regfile[get_dst(iw)] = self.mem.read(self.secondary_regfile[get_src(iw)] + imm);
+                self.primary_regfile[get_dst(iw)] = self.load(self.secondary_regfile[get_src(iw)] + imm);
                 self.secondary_regfile[get_src(iw)] += 1;
             }
             231 => { // pldiy
                 let imm = self.get_imx();
-                self.primary_regfile[get_dst(iw)] = self.mem.read({ self.secondary_regfile[get_src(iw)] += 1; self.secondary_regfile[get_src(iw)] + imm });
+                self.secondary_regfile[get_src(iw)] += 1;
+                self.primary_regfile[get_dst(iw)] = self.load(self.secondary_regfile[get_src(iw)] + imm);
             }
             232 => { // stry
-                self.mem.write(self.secondary_regfile[get_src(iw)], self.primary_regfile[get_dst(iw)]);
+                self.store(self.secondary_regfile[get_src(iw)], self.primary_regfile[get_dst(iw)]);
             }
             233 => { // mstry
-                self.mem.write({ self.secondary_regfile[get_src(iw)] -= 1; self.secondary_regfile[get_src(iw)] }, self.primary_regfile[get_dst(iw)]);
+                self.secondary_regfile[get_src(iw)] -= 1;
+                self.store(self.secondary_regfile[get_src(iw)], self.primary_regfile[get_dst(iw)]);
             }
             234 => {  // stryp
-                self.mem.write({ self.secondary_regfile[get_src(iw)] += 1; self.secondary_regfile[get_src(iw)]-1 }, self.primary_regfile[get_dst(iw)]);
+                self.secondary_regfile[get_src(iw)] += 1;
+                self.store(self.secondary_regfile[get_src(iw)]-1, self.primary_regfile[get_dst(iw)]);
             }
             235 => { // pstry
-                self.mem.write({ self.secondary_regfile[get_src(iw)] += 1; self.secondary_regfile[get_src(iw)]}, self.primary_regfile[get_dst(iw)]);
+                self.secondary_regfile[get_src(iw)] += 1;
+                self.store(self.secondary_regfile[get_src(iw)], self.primary_regfile[get_dst(iw)]);
             }
             236 => { // stiy
                 let imm = self.get_imx();
-                self.mem.write(self.secondary_regfile[get_src(iw)] + imm, self.primary_regfile[get_dst(iw)]);
+                self.store(self.secondary_regfile[get_src(iw)] + imm, self.primary_regfile[get_dst(iw)]);
             }
             237 => { // mstiy
                 let imm = self.get_imx();
-                self.mem.write({ self.secondary_regfile[get_src(iw)] -= 1; self.secondary_regfile[get_src(iw)] } + imm, self.primary_regfile[get_dst(iw)]);
+                self.secondary_regfile[get_src(iw)] -= 1;
+                self.store(self.secondary_regfile[get_src(iw)] + imm, self.primary_regfile[get_dst(iw)]);
             }
             238 => { // stiyp
                 let imm = self.get_imx();
-                self.mem.write(self.secondary_regfile[get_src(iw)] + imm, self.primary_regfile[get_dst(iw)]);
+                self.store(self.secondary_regfile[get_src(iw)] + imm, self.primary_regfile[get_dst(iw)]);
                 self.secondary_regfile[get_src(iw)] += 1;
             }
             239 => { // pstiy
                 let imm = self.get_imx();
-                self.mem.write({ self.secondary_regfile[get_src(iw)] += 1; self.secondary_regfile[get_src(iw)] + imm }, self.primary_regfile[get_dst(iw)]);
+                self.secondary_regfile[get_src(iw)] += 1;
+                self.store(self.secondary_regfile[get_src(iw)] + imm, self.primary_regfile[get_dst(iw)]);
             }
             240..=247 => if self.eval_cond(opcode) { self.reg_ip += sxt8(get_iml(iw))-1 },
-            _ => panic!("[ERR] Invalid instruction: {}; {}", iw, opcode),
+            _ => self.stop = Some(StopReason::InvalidInstruction { ip, instr: (iw & MAGIC_NUMBER) as u16 }),
         }
     }
 }
@@ -624,4 +898,91 @@ fn is_ovf(a: i32, b: i32, sum: i32) -> bool {
 
 fn is_zero(val: i32) -> bool {
     (val&65535) == 0
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::watch::Watchpoint;
+
+    /// run from address 0 until the CPU stops, or give up after `limit` instructions
+    fn run(program: &[u16], limit: usize) -> (CPU, Option<StopReason>) {
+        run_watched(program, &[], limit)
+    }
+
+    /// `run` with `watches` set before the first instruction
+    fn run_watched(program: &[u16], watches: &[Watchpoint], limit: usize) -> (CPU, Option<StopReason>) {
+        let mut cpu = CPU::with_io(IO::detached());
+        cpu.load_prog_words(program);
+        cpu.reset();
+        for watch in watches {
+            cpu.watchpoints.add(*watch);
+        }
+        for _ in 0..limit {
+            if cpu.stop_reason().is_some() {
+                break;
+            }
+            cpu.exec();
+        }
+        let reason = cpu.stop_reason();
+        (cpu, reason)
+    }
+
+    #[test]
+    fn inline_program_runs_without_serial_backend() {
+        // lsi r1, 5; stix r1, [r0 + 0x100]; halt
+        let (cpu, reason) = run(&[0x8051, 0x7f01, 0x0100, 0x0000], 10);
+        assert_eq!(reason, Some(StopReason::Halted));
+        assert_eq!(cpu.peek(0x100), 5);
+    }
+
+    #[test]
+    fn invalid_instruction_stops_with_its_address() {
+        let (_, reason) = run(&[0x8051, 0xff12], 10);
+        assert_eq!(reason, Some(StopReason::InvalidInstruction { ip: 1, instr: 0xff12 }));
+    }
+
+    #[test]
+    fn watchpoints_see_the_address_each_indirect_form_uses() {
+        // lsi r1, 0x12; addiy r2, r0 + 0x100; <form> r1, [r2 (+ 2)]; halt
+        let cases = [
+            (0xe0, WatchKind::Read, 0x100), // ldry
+            (0xe1, WatchKind::Read, 0x0ff), // mldry
+            (0xe2, WatchKind::Read, 0x100), // ldryp
+            (0xe3, WatchKind::Read, 0x101), // pldry
+            (0xe4, WatchKind::Read, 0x102), // ldiy
+            (0xe5, WatchKind::Read, 0x101), // mldiy
+            (0xe6, WatchKind::Read, 0x102), // ldiyp
+            (0xe7, WatchKind::Read, 0x103), // pldiy
+            (0xe8, WatchKind::Write, 0x100), // stry
+            (0xe9, WatchKind::Write, 0x0ff), // mstry
+            (0xea, WatchKind::Write, 0x100), // stryp
+            (0xeb, WatchKind::Write, 0x101), // pstry
+            (0xec, WatchKind::Write, 0x102), // stiy
+            (0xed, WatchKind::Write, 0x101), // mstiy
+            (0xee, WatchKind::Write, 0x102), // stiyp
+            (0xef, WatchKind::Write, 0x103), // pstiy
+        ];
+        let watch = Watchpoint { kind: WatchKind::Access, start: 0x0f0, end: 0x1ff, value: None };
+        for (opcode, kind, addr) in cases {
+            let instr = opcode << 8 | 0x21;
+            let (_, reason) = run_watched(&[0x8121, 0x4302, 0x0100, instr, 0x0002, 0x0000], &[watch], 10);
+            let value = if kind == WatchKind::Write { 0x12 } else { 0 };
+            assert_eq!(reason, Some(StopReason::Watchpoint { kind, addr, value, ip: 3, instr }), "opcode {:#04x}", opcode);
+        }
+    }
+
+    #[test]
+    fn watchpoint_with_a_value_waits_for_that_value() {
+        // lsi r1, 0x12; lsi r3, 0x34; addiy r2, r0 + 0x100; stry r1, [r2]; stry r3, [r2]; halt
+        let watch = Watchpoint { kind: WatchKind::Write, start: 0x100, end: 0x100, value: Some(0x34) };
+        let (_, reason) = run_watched(&[0x8121, 0x8343, 0x4302, 0x0100, 0xe821, 0xe823, 0x0000], &[watch], 10);
+        assert_eq!(reason, Some(StopReason::Watchpoint { kind: WatchKind::Write, addr: 0x100, value: 0x34, ip: 5, instr: 0xe823 }));
+    }
+
+    #[test]
+    fn cpu_can_move_to_another_thread() {
+        fn assert_send<T: Send>() {}
+        assert_send::<CPU>();
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+
+/// Mixed into the seed of the register stream
+const REGISTER_SALT: u64 = 0x5245_4749_5354_4552;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// What memory or registers hold at power-on
+pub enum InitPattern {
+    /// all zero, how the emulator always started
+    #[default]
+    Zero,
+    /// every word the same poison value such as 0xDEAD
+    Fill(u16),
+    /// pseudo-random words, the same sequence every time for the same seed
+    Random(u64),
+}
+
+impl InitPattern {
+    /// `zero`, a fill word like `0xdead`, or `random:<seed>`
+    pub fn from_spec(spec: &str) -> Option<InitPattern> {
+        let number = |text: &str| match text.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => text.parse().ok(),
+        };
+        match spec.split_once(':') {
+            Some(("random", seed)) => number(seed).map(InitPattern::Random),
+            Some(_) => None,
+            None if spec == "zero" => Some(InitPattern::Zero),
+            None => number(spec).and_then(|word| u16::try_from(word).ok()).map(InitPattern::Fill),
+        }
+    }
+
+    /// Endless stream of 16 bit words following the pattern, for memory
+    pub fn words(self) -> impl Iterator<Item = i32> {
+        self.stream(0)
+    }
+
+    /// Like `words` but from a different random sequence, so registers don't repeat low memory
+    /// when both use the same seed
+    pub fn register_words(self) -> impl Iterator<Item = i32> {
+        self.stream(REGISTER_SALT)
+    }
+
+    /// `salt` is mixed into a random seed to pick a separate sequence
+    fn stream(self, salt: u64) -> impl Iterator<Item = i32> {
+        let mut state = match self {
+            InitPattern::Random(seed) => XorShift::new(seed ^ salt),
+            _ => XorShift::new(0),
+        };
+        std::iter::repeat_with(move || match self {
+            InitPattern::Zero => 0,
+            InitPattern::Fill(word) => word as i32,
+            InitPattern::Random(_) => (state.next() >> 48) as i32,
+        })
+    }
+}
+
+impl fmt::Display for InitPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InitPattern::Zero => write!(f, "zero"),
+            InitPattern::Fill(word) => write!(f, "{:#06x}", word),
+            InitPattern::Random(seed) => write!(f, "random:{}", seed),
+        }
+    }
+}
+
+/// xorshift64*, small and the same on every host so seeds reproduce exactly
+struct XorShift(u64);
+
+impl XorShift {
+    /// a zero state would only ever produce zeros, so the seed is mixed first
+    fn new(seed: u64) -> XorShift {
+        const MIX: u64 = 0x9e37_79b9_7f4a_7c15;
+        match seed ^ MIX {
+            0 => XorShift(MIX),
+            state => XorShift(state),
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_repeats_the_same_words() {
+        let first: Vec<i32> = InitPattern::Random(7).words().take(64).collect();
+        assert_eq!(first, InitPattern::Random(7).words().take(64).collect::<Vec<i32>>());
+        assert_ne!(first, InitPattern::Random(8).words().take(64).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn registers_and_memory_differ_for_the_same_seed() {
+        let memory: Vec<i32> = InitPattern::Random(7).words().take(32).collect();
+        let registers: Vec<i32> = InitPattern::Random(7).register_words().take(32).collect();
+        assert_ne!(memory, registers);
+        assert_eq!(InitPattern::Fill(0xdead).register_words().next(), Some(0xdead));
+    }
+}
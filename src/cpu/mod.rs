@@ -1,2 +1,8 @@
 pub mod addressable;
+pub mod bus;
+#[allow(clippy::module_inception)]
 pub mod cpu;
+pub mod init;
+pub mod nvram;
+pub mod perf;
+pub mod watch;
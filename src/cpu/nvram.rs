@@ -0,0 +1,116 @@
+use std::{fs::{self, File}, io::{self, BufWriter}, path::{Path, PathBuf}};
+
+use crate::{export, loader::{self, ImageFormat, LoadError, LoadErrorKind, LoadMode}, MAGIC_NUMBER};
+
+use super::addressable::{Addressable, Loadable};
+
+/// Memory whose contents persist in a host file of big-endian words between runs
+/// - a missing file starts out zeroed, a short one is padded with zeros and a long one truncated
+/// - writes only reach the file on `flush`, which does nothing unless something changed
+pub struct Nvram {
+    path: PathBuf,
+    words: Vec<i32>,
+    dirty: bool,
+}
+
+impl Nvram {
+    /// Open `words` words of NVRAM kept in `path`, the file is created on the first flush
+    pub fn open(path: impl Into<PathBuf>, words: usize) -> Result<Nvram, LoadError> {
+        let path = path.into();
+        let mut contents = match loader::load_file(&path, Some(ImageFormat::Binary), LoadMode::Strict) {
+            Ok(contents) => contents,
+            Err(LoadError { kind: LoadErrorKind::Io(err), .. }) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        contents.resize(words, 0);
+        Ok(Nvram { path, words: contents, dirty: false })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+}
+
+impl Loadable for Nvram {}
+
+impl Addressable for Nvram {
+    fn read(&mut self, loc: i32) -> i32 {
+        self.peek(loc).unwrap_or(0)
+    }
+
+    fn size(&self) -> usize {
+        self.words.len()
+    }
+
+    fn peek(&self, loc: i32) -> Option<i32> {
+        Some(self.words.get((loc & MAGIC_NUMBER) as usize).copied().unwrap_or(0))
+    }
+
+    fn write(&mut self, loc: i32, val: i32) {
+        if let Some(word) = self.words.get_mut((loc & MAGIC_NUMBER) as usize) {
+            self.dirty |= *word != val;
+            *word = val;
+        }
+    }
+
+    /// Replace the file through a temporary next to it so a crash never leaves it half written
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        export::write_binary(&mut out, &self.words)?;
+        out.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a path under the system temp dir no other test uses, with nothing at it yet
+    fn scratch(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("pplus-emu-nvram-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn short_files_are_padded_and_long_ones_truncated() {
+        let path = scratch("resize");
+        fs::write(&path, [0x12, 0x34, 0xab, 0xcd]).unwrap();
+        let padded = Nvram::open(&path, 4).unwrap();
+        let truncated = Nvram::open(&path, 1).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(padded.words, [0x1234, 0xabcd, 0, 0]);
+        assert_eq!(truncated.words, [0x1234]);
+        assert!(!padded.is_dirty() && !truncated.is_dirty());
+    }
+
+    #[test]
+    fn flush_writes_only_when_dirty() {
+        let path = scratch("flush");
+        let mut nvram = Nvram::open(&path, 2).unwrap();
+        nvram.write(1, 0);
+        nvram.flush().unwrap();
+        assert!(!path.exists(), "unchanged NVRAM created its file");
+        nvram.write(1, 0x55aa);
+        assert!(nvram.is_dirty());
+        nvram.flush().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), [0, 0, 0x55, 0xaa]);
+        assert!(!nvram.is_dirty());
+        fs::remove_file(&path).unwrap();
+        nvram.flush().unwrap();
+        assert!(!path.exists(), "clean NVRAM rewrote its file");
+        assert_eq!(Nvram::open(&path, 2).unwrap().words, [0, 0]);
+    }
+}
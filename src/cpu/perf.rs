@@ -0,0 +1,111 @@
+use std::sync::{atomic::{AtomicI32, AtomicU64, Ordering}, Arc};
+
+use crate::io::port::IoDevice;
+
+/// First port of the counter window, `inp` from 0xF0..=0xF7 reads the counter halves
+pub const PORT_PERF_BASE: u8 = 0xf0;
+/// Last port of the counter window
+pub const PORT_PERF_LAST: u8 = 0xf7;
+/// Any `out` to this port clears all counters
+pub const PORT_PERF_RESET: u8 = 0xf8;
+
+/// Ports the counters answer, from `PORT_PERF_BASE` through `PORT_PERF_RESET`
+pub const PERF_PORTS: usize = (PORT_PERF_RESET - PORT_PERF_BASE) as usize + 1;
+
+#[derive(Debug, Default)]
+/// One counter, only ever advanced by the CPU thread, so a relaxed load and store is enough to bump it
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn bump(&self) {
+        self.0.store(self.get().wrapping_add(1), Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        self.0.store(0, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+/// Guest-visible performance counters, shared between the CPU counting and the port device reading them
+/// - `cycles` counts every word moved over the memory bus, fetches and data alike
+/// - `mem_accesses` only counts data loads and stores
+pub struct PerfCounters {
+    pub retired: Counter,
+    pub cycles: Counter,
+    pub skipped: Counter,
+    pub mem_accesses: Counter,
+    latch: AtomicI32,
+}
+
+impl PerfCounters {
+    pub fn new() -> PerfCounters {
+        PerfCounters::default()
+    }
+
+    /// clear all counters and the high half latch
+    pub fn reset(&self) {
+        for counter in [&self.retired, &self.cycles, &self.skipped, &self.mem_accesses] {
+            counter.clear();
+        }
+        self.latch.store(0, Ordering::Relaxed);
+    }
+
+    /// Read one 16 bit half of the low 32 bits of a counter.
+    /// Port offsets go low/high for retired, cycles, skipped and mem_accesses in that order.
+    /// Reading a low half latches its high half and reading any high half returns the latch,
+    /// so a lo-then-hi pair is never torn by the `inp` instructions retiring in between.
+    pub fn read_half(&self, offset: i32) -> i32 {
+        if offset & 1 == 0 {
+            self.latch.store((self.counter(offset) >> 16) as i32 & 0xffff, Ordering::Relaxed);
+            self.counter(offset) as i32 & 0xffff
+        } else {
+            self.latch.load(Ordering::Relaxed)
+        }
+    }
+
+    /// The half `read_half` would return, without latching
+    pub fn peek_half(&self, offset: i32) -> i32 {
+        if offset & 1 == 0 {
+            self.counter(offset) as i32 & 0xffff
+        } else {
+            self.latch.load(Ordering::Relaxed)
+        }
+    }
+
+    fn counter(&self, offset: i32) -> u64 {
+        match offset >> 1 {
+            0 => self.retired.get(),
+            1 => self.cycles.get(),
+            2 => self.skipped.get(),
+            _ => self.mem_accesses.get(),
+        }
+    }
+}
+
+/// Attached at `PORT_PERF_BASE`, so ports are offsets into the counter window
+impl IoDevice for Arc<PerfCounters> {
+    fn read(&mut self, port: u8) -> i32 {
+        match port {
+            _ if port <= PORT_PERF_LAST - PORT_PERF_BASE => self.read_half(port as i32),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, port: u8, _val: i32) {
+        if port == PORT_PERF_RESET - PORT_PERF_BASE {
+            self.reset();
+        }
+    }
+
+    fn peek(&self, port: u8) -> Option<i32> {
+        match port {
+            _ if port <= PORT_PERF_LAST - PORT_PERF_BASE => Some(self.peek_half(port as i32)),
+            _ => Some(0),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Which data accesses a watchpoint reacts to
+pub enum WatchKind {
+    Read,
+    Write,
+    /// reads and writes
+    Access,
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchKind::Read => write!(f, "read"),
+            WatchKind::Write => write!(f, "write"),
+            WatchKind::Access => write!(f, "access"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Data accesses to `start..=end` that stop the CPU
+/// - with a `value` only accesses reading or writing exactly that word match
+pub struct Watchpoint {
+    pub kind: WatchKind,
+    pub start: u16,
+    pub end: u16,
+    pub value: Option<u16>,
+}
+
+impl Watchpoint {
+    /// Watch a single address
+    pub fn new(kind: WatchKind, addr: u16) -> Watchpoint {
+        Watchpoint { kind, start: addr, end: addr, value: None }
+    }
+
+    fn matches(&self, kind: WatchKind, addr: u16, value: u16) -> bool {
+        (self.kind == WatchKind::Access || self.kind == kind)
+            && (self.start..=self.end).contains(&addr)
+            && self.value.is_none_or(|v| v == value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The access that triggered a watchpoint, `kind` is `Read` or `Write`
+pub struct WatchHit {
+    pub kind: WatchKind,
+    pub addr: u16,
+    pub value: u16,
+}
+
+#[derive(Debug, Default)]
+/// Watchpoints checked on every data memory access made by an instruction
+/// - instruction and immediate fetches are not data accesses and never match
+/// - the first hit is kept until taken so the instruction can finish first
+pub struct Watchpoints {
+    list: Vec<Watchpoint>,
+    hit: Option<WatchHit>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Watchpoints {
+        Watchpoints::default()
+    }
+
+    pub fn add(&mut self, watch: Watchpoint) {
+        self.list.push(watch);
+    }
+
+    /// Remove every watchpoint equal to `watch`, returns whether any were removed
+    pub fn remove(&mut self, watch: &Watchpoint) -> bool {
+        let len = self.list.len();
+        self.list.retain(|w| w != watch);
+        self.list.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+        self.hit = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.list.iter()
+    }
+
+    /// Record an access of `kind`, which must be `Read` or `Write`
+    pub fn check(&mut self, kind: WatchKind, addr: u16, value: u16) {
+        if self.hit.is_none() && self.list.iter().any(|w| w.matches(kind, addr, value)) {
+            self.hit = Some(WatchHit { kind, addr, value });
+        }
+    }
+
+    pub fn take_hit(&mut self) -> Option<WatchHit> {
+        self.hit.take()
+    }
+}
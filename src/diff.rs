@@ -0,0 +1,119 @@
+use std::io::{self, Write};
+
+use crate::{export::packed_chars, symbols::SymbolTable, MAGIC_NUMBER};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A run of consecutive changed words starting at `start`
+pub struct DiffRange {
+    pub start: u16,
+    pub old: Vec<i32>,
+    pub new: Vec<i32>,
+}
+
+impl DiffRange {
+    pub fn len(&self) -> usize {
+        self.new.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.new.is_empty()
+    }
+
+    /// Last address in the range
+    pub fn end(&self) -> u16 {
+        self.start + self.len() as u16 - 1
+    }
+}
+
+/// Compare two memory states word by word, `before[0]` and `after[0]` both live at address `base`
+/// - words past the end of the shorter state count as 0
+pub fn diff_words(base: u16, before: &[i32], after: &[i32]) -> Vec<DiffRange> {
+    let len = before.len().max(after.len()).min(65536 - base as usize);
+    let word = |words: &[i32], pos: usize| words.get(pos).map_or(0, |w| w & MAGIC_NUMBER);
+    let mut ranges: Vec<DiffRange> = Vec::new();
+    for pos in 0..len {
+        let (old, new) = (word(before, pos), word(after, pos));
+        if old == new {
+            continue;
+        }
+        let addr = base + pos as u16;
+        match ranges.last_mut() {
+            Some(range) if range.end() as usize + 1 == addr as usize => {
+                range.old.push(old);
+                range.new.push(new);
+            }
+            _ => ranges.push(DiffRange { start: addr, old: vec![old], new: vec![new] }),
+        }
+    }
+    ranges
+}
+
+/// Each range as a header naming where it starts followed by old and new words eight at a time
+/// - the packed text of the words is shown when at least half the old or new characters are printable
+pub fn write_diff(out: &mut impl Write, ranges: &[DiffRange], symbols: &SymbolTable) -> io::Result<()> {
+    if ranges.is_empty() {
+        return writeln!(out, "no words changed");
+    }
+    for range in ranges {
+        writeln!(out, "{:04x}-{:04x} {}, {} word{}", range.start, range.end(), symbols.describe(range.start), range.len(), if range.len() == 1 { "" } else { "s" })?;
+        let printable = |words: &[i32]| words.iter().flat_map(|w| packed_chars(*w)).filter(|c| *c != '.').count();
+        let show_text = printable(&range.old).max(printable(&range.new)) >= range.len();
+        for (idx, (old, new)) in range.old.chunks(8).zip(range.new.chunks(8)).enumerate() {
+            let addr = range.start as usize + idx * 8;
+            write_row(out, addr, "-", old, show_text)?;
+            write_row(out, addr, "+", new, show_text)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_row(out: &mut impl Write, addr: usize, sign: &str, words: &[i32], show_text: bool) -> io::Result<()> {
+    let hex = words.iter().map(|w| format!("{:04x}", w)).collect::<Vec<String>>();
+    if show_text {
+        let text = words.iter().flat_map(|w| packed_chars(*w)).collect::<String>();
+        writeln!(out, "  {}{:04x}: {:<39} |{}|", sign, addr, hex.join(" "), text)
+    } else {
+        writeln!(out, "  {}{:04x}: {}", sign, addr, hex.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjacent_changes_group_into_one_range() {
+        let before = [0, 1, 2, 3, 4, 5, 6];
+        let after = [0, 9, 9, 3, 4, 8, 6, 7];
+        let ranges = diff_words(0x100, &before, &after);
+        assert_eq!(ranges, vec![
+            DiffRange { start: 0x101, old: vec![1, 2], new: vec![9, 9] },
+            DiffRange { start: 0x105, old: vec![5], new: vec![8] },
+            DiffRange { start: 0x107, old: vec![0], new: vec![7] },
+        ]);
+        assert_eq!((ranges[0].end(), ranges[0].len()), (0x102, 2));
+    }
+
+    #[test]
+    fn only_the_low_sixteen_bits_count() {
+        assert!(diff_words(0, &[0x1_0005], &[5]).is_empty());
+    }
+
+    #[test]
+    fn diff_stops_at_the_end_of_the_address_space() {
+        let ranges = diff_words(0xfffe, &[], &[1, 2, 3]);
+        assert_eq!(ranges, vec![DiffRange { start: 0xfffe, old: vec![0, 0], new: vec![1, 2] }]);
+    }
+
+    #[test]
+    fn written_diff_names_the_range_and_shows_text() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("msg", 0x20);
+        let mut out = Vec::new();
+        write_diff(&mut out, &diff_words(0x20, &[0, 0], &[0x4869, 0x2100]), &symbols).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("0020-0021 msg (0x0020), 2 words\n  -0020: {:<39} |....|\n  +0020: {:<39} |Hi!.|\n", "0000 0000", "4869 2100"));
+        let mut out = Vec::new();
+        write_diff(&mut out, &[], &symbols).unwrap();
+        assert_eq!(out, b"no words changed\n");
+    }
+}
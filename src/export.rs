@@ -0,0 +1,153 @@
+use std::io::{self, Write};
+
+use crate::{symbols::SymbolTable, MAGIC_NUMBER};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Formats memory can be written out in
+pub enum DumpFormat {
+    /// Logisim `v2.0 raw`, loadable into a Logisim RAM and back into the emulator
+    LogisimRaw,
+    /// big-endian 16 bit words
+    Binary,
+    /// human-readable words with the packed two-characters-per-word text alongside
+    HexDump,
+}
+
+impl DumpFormat {
+    pub fn from_name(name: &str) -> Option<DumpFormat> {
+        match name {
+            "logisim" | "logisim16" => Some(DumpFormat::LogisimRaw),
+            "bin" | "binary" => Some(DumpFormat::Binary),
+            "hexdump" => Some(DumpFormat::HexDump),
+            _ => None,
+        }
+    }
+}
+
+/// Write `words`, the first of which lives at address `base`, in `format`
+/// - only the hexdump has room to show `symbols`
+pub fn write_dump(out: &mut impl Write, format: DumpFormat, base: u16, words: &[i32], symbols: &SymbolTable) -> io::Result<()> {
+    match format {
+        DumpFormat::LogisimRaw => write_logisim(out, words),
+        DumpFormat::Binary => write_binary(out, words),
+        DumpFormat::HexDump => write_hexdump(out, base, words, symbols),
+    }
+}
+
+/// Logisim `v2.0 raw`, eight words a line with runs of four or more folded into `N*V`
+pub fn write_logisim(out: &mut impl Write, words: &[i32]) -> io::Result<()> {
+    writeln!(out, "v2.0 raw")?;
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < words.len() {
+        let val = words[pos] & MAGIC_NUMBER;
+        let run = words[pos..].iter().take_while(|w| *w & MAGIC_NUMBER == val).count();
+        if run >= 4 {
+            tokens.push(format!("{}*{:x}", run, val));
+            pos += run;
+        } else {
+            tokens.push(format!("{:04x}", val));
+            pos += 1;
+        }
+    }
+    for line in tokens.chunks(8) {
+        writeln!(out, "{}", line.join(" "))?;
+    }
+    Ok(())
+}
+
+pub fn write_binary(out: &mut impl Write, words: &[i32]) -> io::Result<()> {
+    let bytes = words.iter().flat_map(|w| [(w >> 8) as u8, *w as u8]).collect::<Vec<u8>>();
+    out.write_all(&bytes)
+}
+
+/// Eight words a line followed by any labels defined on it
+/// - identical unlabelled lines after the first collapse into `*`
+pub fn write_hexdump(out: &mut impl Write, base: u16, words: &[i32], symbols: &SymbolTable) -> io::Result<()> {
+    let mut prev: Option<&[i32]> = None;
+    let mut collapsed = false;
+    for (idx, line) in words.chunks(8).enumerate() {
+        let addr = base as usize + idx * 8;
+        let labels = (addr..addr + line.len())
+            .filter_map(|a| symbols.name_at(a as u16))
+            .collect::<Vec<&str>>();
+        if prev == Some(line) && labels.is_empty() {
+            if !collapsed {
+                writeln!(out, "*")?;
+                collapsed = true;
+            }
+            continue;
+        }
+        prev = Some(line);
+        collapsed = false;
+        let hex = line.iter().map(|w| format!("{:04x}", w & MAGIC_NUMBER)).collect::<Vec<String>>();
+        let text = line.iter().flat_map(|w| packed_chars(*w)).collect::<String>();
+        write!(out, "{:04x}: {:<39} |{:<16}|", addr, hex.join(" "), text)?;
+        if !labels.is_empty() {
+            write!(out, " {}", labels.join(", "))?;
+        }
+        writeln!(out)?;
+    }
+    if collapsed {
+        writeln!(out, "{:04x}:", base as usize + words.len())?;
+    }
+    Ok(())
+}
+
+/// The two characters of a word as `print_packed_string` sends them, high byte first
+/// - unprintable bytes show as `.`
+pub fn packed_chars(word: i32) -> [char; 2] {
+    let printable = |b: u8| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' };
+    [printable((word >> 8) as u8), printable(word as u8)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::{self, ImageFormat, LoadMode};
+
+    fn logisim(words: &[i32]) -> String {
+        let mut out = Vec::new();
+        write_logisim(&mut out, words).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn logisim_folds_runs_of_four_or_more() {
+        assert_eq!(logisim(&[1, 2, 2, 2, 0, 0, 0, 0, 0x1abcd]), "v2.0 raw\n0001 0002 0002 0002 4*0 abcd\n");
+        assert_eq!(logisim(&[7; 9]), "v2.0 raw\n9*7\n");
+    }
+
+    #[test]
+    fn logisim_round_trips_through_the_loader() {
+        let mut words = (0..40).map(|w| w * 0x111).collect::<Vec<i32>>();
+        words.extend([0; 300]);
+        words.extend([0xffff, 0xffff, 0xffff, 0xffff, 5, 5]);
+        let image = logisim(&words);
+        assert!(image.contains("300*0") && image.contains("4*ffff"));
+        assert_eq!(image.lines().skip(1).map(|line| line.split(' ').count()).max(), Some(8));
+        assert_eq!(loader::parse(image.as_bytes(), ImageFormat::LogisimRaw, LoadMode::Strict).unwrap(), words);
+    }
+
+    #[test]
+    fn binary_is_big_endian() {
+        let mut out = Vec::new();
+        write_binary(&mut out, &[0x1234, 0x00ff]).unwrap();
+        assert_eq!(out, [0x12, 0x34, 0x00, 0xff]);
+    }
+
+    #[test]
+    fn hexdump_collapses_repeated_lines_and_shows_labels() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("buf", 0x10);
+        let mut words = vec![0x4869; 8];
+        words.extend([0; 24]);
+        let mut out = Vec::new();
+        write_hexdump(&mut out, 0, &words, &symbols).unwrap();
+        let dump = String::from_utf8(out).unwrap();
+        let lines = dump.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], format!("0000: {} |{}|", ["4869"; 8].join(" "), "Hi".repeat(8)));
+        assert!(lines[1].starts_with("0008: 0000") && lines[2].ends_with("| buf"));
+        assert_eq!(&lines[3..], ["*", "0020:"]);
+    }
+}
@@ -0,0 +1,47 @@
+use std::{sync::Arc, thread};
+
+use crate::BlockingQueue;
+
+use super::port::IoDevice;
+
+/// `out` to this port prints on the host's stdout
+pub const PORT_DBGCON: u8 = 0x00;
+
+/// Debug console printing what the guest writes, a packed pair of characters per word
+/// - printing happens on its own thread so a slow stdout never stalls the CPU
+pub struct DebugConsole {
+    queue: Arc<BlockingQueue<i32>>,
+}
+
+impl DebugConsole {
+    /// Start the printer thread
+    pub fn start() -> DebugConsole {
+        let queue = Arc::new(BlockingQueue::new());
+        let console_io = queue.clone();
+        thread::spawn(move || {
+            loop {
+                let x = console_io.de_q();
+                print!("{}", ((x>>8)&255) as u8 as char);
+                if x & 255 > 0 {
+                    print!("{}", (x&255) as u8 as char);
+                }
+            }
+        });
+        DebugConsole { queue }
+    }
+}
+
+impl IoDevice for DebugConsole {
+    /// Write-only, reads return 0
+    fn read(&mut self, _port: u8) -> i32 {
+        0
+    }
+
+    fn write(&mut self, _port: u8, val: i32) {
+        self.queue.en_q(val);
+    }
+
+    fn peek(&self, _port: u8) -> Option<i32> {
+        Some(0)
+    }
+}
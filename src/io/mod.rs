@@ -0,0 +1,196 @@
+pub mod console;
+pub mod port;
+pub mod pty;
+pub mod serial;
+pub mod server;
+pub mod telnet;
+pub mod terminal;
+pub mod watchdog;
+
+use std::{fmt, io, net::{Ipv4Addr, SocketAddr}, path::{Path, PathBuf}, thread, sync::Arc};
+
+use crate::{cpu::{addressable::Addressable, perf::{PerfCounters, PERF_PORTS, PORT_PERF_BASE}}, BlockingQueue};
+
+use self::{console::{DebugConsole, PORT_DBGCON}, port::{IoDevice, PortMap, PORT_COUNT}, pty::PtyIO, serial::{SerialPorts, PORT_TTYPKD_POLL, SERIAL_PORTS}, server::{Listener, Protocol, SerialServer}, terminal::{key_name, TerminalIO}, watchdog::{Watchdog, PORT_WATCHDOG}};
+
+/// Where the telnet server listens unless told otherwise
+pub const DEFAULT_TELNET_ADDR: SocketAddr = SocketAddr::new(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), 23);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What the guest's serial ports `ttyraw` and `ttypkd` are connected to
+pub enum SerialBackend {
+    /// a telnet server listening on `addr`, port 0 lets the OS pick a free port
+    /// - with `observer` a second, read-only client may watch alongside the interactive one
+    Telnet { addr: SocketAddr, observer: bool },
+    /// like `Telnet` but plain bytes both ways, no option negotiation and no IAC handling
+    RawTcp { addr: SocketAddr, observer: bool },
+    /// plain bytes over a Unix domain socket created at `path`
+    Unix { path: PathBuf, observer: bool },
+    /// the emulator's own stdin and stdout in raw mode, `escape` returns control to the host
+    Terminal { escape: u8 },
+    /// a new Linux pseudo-terminal for tools like `screen` to attach to
+    Pty,
+}
+
+impl Default for SerialBackend {
+    fn default() -> SerialBackend {
+        SerialBackend::Telnet { addr: DEFAULT_TELNET_ADDR, observer: false }
+    }
+}
+
+impl fmt::Display for SerialBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerialBackend::Telnet { addr, .. } => write!(f, "telnet server on {}", addr),
+            SerialBackend::RawTcp { addr, .. } => write!(f, "raw TCP server on {}", addr),
+            SerialBackend::Unix { path, .. } => write!(f, "Unix socket server on {}", path.display()),
+            SerialBackend::Terminal { escape } => write!(f, "terminal on stdin, {} to escape", key_name(*escape)),
+            SerialBackend::Pty => write!(f, "pseudo-terminal"),
+        }
+    }
+}
+
+/// what keeps a started serial backend going and what it tells about itself
+enum SerialHandle {
+    Socket(SocketAddr),
+    Unix(PathBuf),
+    Terminal(TerminalIO),
+    Pty(PtyIO),
+}
+
+/// The I/O port space and the host side of the devices attached to it
+/// - `perf` and `watchdog` are shared with their devices so the CPU can count and tick them directly
+pub struct IO {
+    telnet_input: Arc<BlockingQueue<i32>>,
+    telnet_output: Arc<BlockingQueue<i32>>,
+    serial: Option<SerialHandle>,
+    pub ports: PortMap,
+    pub perf: Arc<PerfCounters>,
+    watchdog: Arc<Watchdog>,
+}
+
+impl Addressable for IO {
+    fn write(&mut self, loc: i32, val: i32) {
+        self.ports.write(loc as u8, val);
+    }
+
+    fn read(&mut self, loc: i32) -> i32 {
+        self.ports.read(loc as u8)
+    }
+
+    fn size(&self) -> usize {
+        PORT_COUNT
+    }
+
+    fn peek(&self, loc: i32) -> Option<i32> {
+        self.ports.peek(loc as u8)
+    }
+}
+
+impl IO {
+    /// I/O with the serial ports on the default telnet server
+    /// - if it can't be started the problem is printed and the serial ports stay unconnected
+    pub fn init() -> IO {
+        IO::with_serial(SerialBackend::default()).unwrap_or_else(|err| {
+            println!("[ERR] Starting {} failed: {}", SerialBackend::default(), err);
+            IO::detached()
+        })
+    }
+
+    /// I/O with the serial ports connected to `backend`, failing if it can't be set up
+    pub fn with_serial(backend: SerialBackend) -> io::Result<IO> {
+        match backend {
+            SerialBackend::Telnet { addr, observer } => IO::serve(Listener::tcp(addr)?, Protocol::Telnet, observer),
+            SerialBackend::RawTcp { addr, observer } => IO::serve(Listener::tcp(addr)?, Protocol::Raw, observer),
+            SerialBackend::Unix { path, observer } => IO::serve(Listener::unix(&path)?, Protocol::Raw, observer),
+            SerialBackend::Terminal { escape } => {
+                let mut io = IO::detached();
+                io.serial = Some(SerialHandle::Terminal(TerminalIO::start(io.telnet_input.clone(), io.telnet_output.clone(), escape)?));
+                Ok(io)
+            }
+            SerialBackend::Pty => {
+                let mut io = IO::detached();
+                io.serial = Some(SerialHandle::Pty(PtyIO::open(io.telnet_input.clone(), io.telnet_output.clone())?));
+                Ok(io)
+            }
+        }
+    }
+
+    pub fn watchdog(&self) -> &Watchdog {
+        &self.watchdog
+    }
+
+    /// Swap in a differently configured watchdog, on its port too unless that device was removed
+    pub fn set_watchdog(&mut self, watchdog: Watchdog) {
+        self.watchdog = Arc::new(watchdog);
+        let _ = self.ports.replace("watchdog", Box::new(self.watchdog.clone()));
+    }
+
+    /// Whether the user pressed the terminal backend's escape key
+    pub fn escape_requested(&self) -> bool {
+        matches!(&self.serial, Some(SerialHandle::Terminal(terminal)) if terminal.escaped())
+    }
+
+    /// Give the terminal back to the host in its original mode, the guest gets no more keys
+    pub fn release_terminal(&mut self) {
+        if let Some(SerialHandle::Terminal(_)) = self.serial {
+            self.serial = None;
+        }
+    }
+
+    /// Address the serial server actually listens on, with the port the OS picked for port 0
+    pub fn serial_addr(&self) -> Option<SocketAddr> {
+        match self.serial {
+            Some(SerialHandle::Socket(addr)) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Device the pty backend created or socket the Unix backend listens on, for a client to open
+    pub fn serial_path(&self) -> Option<&Path> {
+        match &self.serial {
+            Some(SerialHandle::Pty(pty)) => Some(pty.path()),
+            Some(SerialHandle::Unix(path)) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// serial ports served to clients of `listener` for the rest of the run
+    fn serve(listener: Listener, protocol: Protocol, observer: bool) -> io::Result<IO> {
+        let mut io = IO::detached();
+        let handle = match &listener {
+            Listener::Tcp(tcp) => SerialHandle::Socket(tcp.local_addr()?),
+            Listener::Unix(_, path) => SerialHandle::Unix(path.clone()),
+        };
+        let mut serv = SerialServer::new(listener, protocol, io.telnet_input.clone(), io.telnet_output.clone());
+        serv.allow_observer(observer);
+        io.serial = Some(handle);
+        thread::spawn(move || serv.serve());
+        Ok(io)
+    }
+
+    /// I/O with the standard devices on their ports but nothing serving the serial line
+    /// - binds no socket and opens no terminal or pty, for tests and embedding
+    /// - serial output piles up unread and serial reads wait forever
+    pub fn detached() -> IO {
+        let mut io = IO {
+            telnet_input: Arc::new(BlockingQueue::new()),
+            telnet_output: Arc::new(BlockingQueue::new()),
+            serial: None,
+            ports: PortMap::new(),
+            perf: Arc::new(PerfCounters::new()),
+            watchdog: Arc::new(Watchdog::disabled()),
+        };
+        let serial = SerialPorts::new(io.telnet_input.clone(), io.telnet_output.clone());
+        let devices: [(&str, u8, usize, Box<dyn IoDevice + Send>); 4] = [
+            ("dbgcon", PORT_DBGCON, 1, Box::new(DebugConsole::start())),
+            ("perf", PORT_PERF_BASE, PERF_PORTS, Box::new(io.perf.clone())),
+            ("watchdog", PORT_WATCHDOG, 1, Box::new(io.watchdog.clone())),
+            ("serial", PORT_TTYPKD_POLL, SERIAL_PORTS, Box::new(serial)),
+        ];
+        for (name, first, count, device) in devices {
+            io.ports.attach(name, first, count, device).expect("standard devices don't overlap");
+        }
+        io
+    }
+}
@@ -0,0 +1,228 @@
+use std::fmt;
+
+use crate::route::RouteTable;
+
+/// Number of ports `inp` and `out` can reach through their 8 bit port field
+pub const PORT_COUNT: usize = 256;
+
+/// A peripheral answering `inp` and `out` on a run of consecutive ports
+/// - `port` counts from the first port the device is attached at, so the same device works at any base
+pub trait IoDevice {
+    /// Value an `inp` from `port` gets, which may consume it, like a byte from a receive register
+    fn read(&mut self, port: u8) -> i32;
+
+    /// Take the value of an `out` to `port`
+    fn write(&mut self, port: u8, val: i32);
+
+    /// What `read` would return, without consuming anything
+    /// - the default None says only a real `inp` can tell, as for input still waiting in a queue
+    fn peek(&self, _port: u8) -> Option<i32> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An `inp` or `out` that reached no device
+pub struct UnmappedAccess {
+    pub port: u8,
+    /// the value written, None for a read
+    pub value: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Why a device could not be attached
+pub enum PortError {
+    /// a port in the range already belongs to the named device
+    Conflict { port: u8, owner: String },
+    /// the range runs past the last port or is empty
+    OutOfRange { first: u8, count: usize },
+    /// no device of that name is attached
+    NoDevice(String),
+}
+
+impl fmt::Display for PortError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PortError::Conflict { port, owner } => write!(f, "port {:#04x} is already used by {}", port, owner),
+            PortError::OutOfRange { first, count } => write!(f, "{} ports from {:#04x} don't fit in the port space", count, first),
+            PortError::NoDevice(name) => write!(f, "no device called {}", name),
+        }
+    }
+}
+
+impl std::error::Error for PortError {}
+
+/// A device attached to `count` ports from `first`
+struct Port {
+    name: String,
+    first: u8,
+    count: usize,
+    device: Box<dyn IoDevice + Send>,
+}
+
+/// I/O port space routing each of the 256 ports to at most one device
+/// - unlike the memory bus, devices never overlap, attaching over a used port fails
+/// - reads from unmapped ports return 0 and writes to them are dropped, optionally recording the access
+pub struct PortMap {
+    ports: Vec<Port>,
+    routes: RouteTable,
+    log_unmapped: bool,
+    unmapped: Option<UnmappedAccess>,
+}
+
+impl PortMap {
+    /// Port space with nothing attached
+    pub fn new() -> PortMap {
+        PortMap { ports: Vec::new(), routes: RouteTable::new(PORT_COUNT), log_unmapped: false, unmapped: None }
+    }
+
+    /// Attach `device` to `count` ports from `first`, which must all be free
+    pub fn attach(&mut self, name: &str, first: u8, count: usize, device: Box<dyn IoDevice + Send>) -> Result<(), PortError> {
+        if count == 0 || first as usize + count > PORT_COUNT {
+            return Err(PortError::OutOfRange { first, count });
+        }
+        let range = first as usize..first as usize + count;
+        if let Some((port, (idx, _))) = range.clone().find_map(|port| Some((port, self.routes.route(port)?))) {
+            return Err(PortError::Conflict { port: port as u8, owner: self.ports[idx].name.clone() });
+        }
+        self.ports.push(Port { name: name.to_string(), first, count, device });
+        self.rebuild();
+        Ok(())
+    }
+
+    /// Swap the device called `name` for `device` on the same ports, returns the old one
+    pub fn replace(&mut self, name: &str, device: Box<dyn IoDevice + Send>) -> Result<Box<dyn IoDevice + Send>, PortError> {
+        let port = self.ports.iter_mut().find(|p| p.name == name).ok_or_else(|| PortError::NoDevice(name.to_string()))?;
+        Ok(std::mem::replace(&mut port.device, device))
+    }
+
+    /// Detach the device called `name`, leaving its ports unmapped
+    pub fn remove(&mut self, name: &str) -> Result<Box<dyn IoDevice + Send>, PortError> {
+        let idx = self.ports.iter().position(|p| p.name == name).ok_or_else(|| PortError::NoDevice(name.to_string()))?;
+        let port = self.ports.remove(idx);
+        self.rebuild();
+        Ok(port.device)
+    }
+
+    /// Name, first port and port count of every device, in port order
+    pub fn devices(&self) -> impl Iterator<Item = (&str, u8, usize)> {
+        let mut devices: Vec<_> = self.ports.iter().map(|p| (p.name.as_str(), p.first, p.count)).collect();
+        devices.sort_by_key(|(_, first, _)| *first);
+        devices.into_iter()
+    }
+
+    /// Record accesses to unmapped ports for `take_unmapped`, off by default
+    pub fn set_log_unmapped(&mut self, log: bool) {
+        self.log_unmapped = log;
+    }
+
+    /// The last access to an unmapped port since this was called, when logging is on
+    pub fn take_unmapped(&mut self) -> Option<UnmappedAccess> {
+        self.unmapped.take()
+    }
+
+    pub fn read(&mut self, port: u8) -> i32 {
+        match self.route(port) {
+            Some((idx, offset)) => self.ports[idx].device.read(offset),
+            None => {
+                self.record(port, None);
+                0
+            }
+        }
+    }
+
+    pub fn write(&mut self, port: u8, val: i32) {
+        match self.route(port) {
+            Some((idx, offset)) => self.ports[idx].device.write(offset, val),
+            None => self.record(port, Some(val)),
+        }
+    }
+
+    /// Unmapped ports peek as 0 like they read
+    pub fn peek(&self, port: u8) -> Option<i32> {
+        match self.route(port) {
+            Some((idx, offset)) => self.ports[idx].device.peek(offset),
+            None => Some(0),
+        }
+    }
+
+    fn record(&mut self, port: u8, value: Option<i32>) {
+        if self.log_unmapped {
+            self.unmapped = Some(UnmappedAccess { port, value });
+        }
+    }
+
+    fn rebuild(&mut self) {
+        self.routes.rebuild(self.ports.iter().map(|p| (p.first as usize, p.count)));
+    }
+
+    fn route(&self, port: u8) -> Option<(usize, u8)> {
+        self.routes.route(port as usize).map(|(idx, offset)| (idx, offset as u8))
+    }
+}
+
+impl Default for PortMap {
+    fn default() -> PortMap {
+        PortMap::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// answers every port with `value` plus the port number
+    struct Fixed(i32);
+
+    impl IoDevice for Fixed {
+        fn read(&mut self, port: u8) -> i32 {
+            self.0 + port as i32
+        }
+
+        fn write(&mut self, _port: u8, val: i32) {
+            self.0 = val;
+        }
+    }
+
+    #[test]
+    fn devices_see_ports_from_their_first_one() {
+        let mut ports = PortMap::new();
+        ports.attach("a", 0x10, 4, Box::new(Fixed(0x100))).unwrap();
+        assert_eq!((ports.read(0x10), ports.read(0x13)), (0x100, 0x103));
+        ports.write(0x12, 0x200);
+        assert_eq!(ports.read(0x11), 0x201);
+    }
+
+    #[test]
+    fn attach_rejects_used_or_missing_ports() {
+        let mut ports = PortMap::new();
+        ports.attach("a", 0x10, 4, Box::new(Fixed(0))).unwrap();
+        assert_eq!(ports.attach("b", 0x0e, 4, Box::new(Fixed(0))), Err(PortError::Conflict { port: 0x10, owner: "a".to_string() }));
+        assert_eq!(ports.attach("b", 0xff, 2, Box::new(Fixed(0))), Err(PortError::OutOfRange { first: 0xff, count: 2 }));
+        ports.attach("b", 0x0e, 2, Box::new(Fixed(0))).unwrap();
+        assert_eq!(ports.devices().collect::<Vec<_>>(), vec![("b", 0x0e, 2), ("a", 0x10, 4)]);
+    }
+
+    #[test]
+    fn replace_and_remove_by_name() {
+        let mut ports = PortMap::new();
+        ports.attach("a", 0x10, 1, Box::new(Fixed(1))).unwrap();
+        ports.replace("a", Box::new(Fixed(2))).unwrap();
+        assert_eq!(ports.read(0x10), 2);
+        ports.remove("a").unwrap();
+        assert_eq!(ports.read(0x10), 0);
+        assert!(ports.remove("a").is_err());
+    }
+
+    #[test]
+    fn unmapped_accesses_are_recorded_only_when_logging() {
+        let mut ports = PortMap::new();
+        ports.write(0x42, 5);
+        assert_eq!(ports.take_unmapped(), None);
+        ports.set_log_unmapped(true);
+        ports.write(0x42, 5);
+        assert_eq!(ports.take_unmapped(), Some(UnmappedAccess { port: 0x42, value: Some(5) }));
+        ports.read(0x43);
+        assert_eq!(ports.take_unmapped(), Some(UnmappedAccess { port: 0x43, value: None }));
+    }
+}
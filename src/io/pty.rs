@@ -0,0 +1,103 @@
+use std::{fs::File, io, path::{Path, PathBuf}, sync::Arc};
+
+use crate::BlockingQueue;
+
+/// A pseudo-terminal whose slave side, `/dev/pts/N`, stands in for the board's UART
+/// - tools like `screen` or `picocom` attach to `path()`, the guest sees what they type
+/// - a slave handle is held open so the master keeps working while no tool is attached
+pub struct PtyIO {
+    path: PathBuf,
+    _slave: File,
+}
+
+impl PtyIO {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::{ffi::{c_char, c_int, CStr}, fs::{File, OpenOptions}, io, os::{fd::FromRawFd, unix::fs::OpenOptionsExt}, path::PathBuf, process::Command};
+
+    const O_RDWR: c_int = 0o2;
+
+    /// the generic Linux value, MIPS, SPARC, Alpha and PA-RISC number their open flags differently
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64", target_arch = "riscv32", target_arch = "riscv64", target_arch = "powerpc", target_arch = "powerpc64", target_arch = "s390x", target_arch = "loongarch64"))]
+    const O_NOCTTY: Option<c_int> = Some(0o400);
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64", target_arch = "riscv32", target_arch = "riscv64", target_arch = "powerpc", target_arch = "powerpc64", target_arch = "s390x", target_arch = "loongarch64")))]
+    const O_NOCTTY: Option<c_int> = None;
+
+    extern "C" {
+        fn posix_openpt(flags: c_int) -> c_int;
+        fn grantpt(fd: c_int) -> c_int;
+        fn unlockpt(fd: c_int) -> c_int;
+        fn ptsname_r(fd: c_int, buf: *mut c_char, len: usize) -> c_int;
+    }
+
+    /// Create a pty in raw mode, returns the master and an open slave with its path
+    pub fn open() -> io::Result<(File, File, PathBuf)> {
+        let no_ctty = O_NOCTTY.ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "pty backend doesn't know the open flags of this architecture"))?;
+        // SAFETY: plain libc calls on a descriptor we own, `buf` outlives `ptsname_r` and is NUL-terminated on success
+        let (master, path) = unsafe {
+            let fd = posix_openpt(O_RDWR | no_ctty);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let master = File::from_raw_fd(fd);
+            if grantpt(fd) != 0 || unlockpt(fd) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut buf = [0 as c_char; 64];
+            let err = ptsname_r(fd, buf.as_mut_ptr(), buf.len());
+            if err != 0 {
+                return Err(io::Error::from_raw_os_error(err));
+            }
+            (master, PathBuf::from(CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()))
+        };
+        let slave = OpenOptions::new().read(true).write(true).custom_flags(no_ctty).open(&path)?;
+        let status = Command::new("stty").arg("-F").arg(&path).args(["raw", "-echo"]).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("stty -F {} raw -echo failed", path.display())));
+        }
+        Ok((master, slave, path))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl PtyIO {
+    /// Create the pty and start moving bytes between its master side and the queues
+    pub fn open(inbound: Arc<BlockingQueue<i32>>, outbound: Arc<BlockingQueue<i32>>) -> io::Result<PtyIO> {
+        use std::{io::{Read, Write}, thread};
+
+        let (mut master, slave, path) = sys::open()?;
+        let mut reader = master.try_clone()?;
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok(len @ 1..) = reader.read(&mut buf) {
+                for byte in &buf[..len] {
+                    inbound.en_q(*byte as i32);
+                }
+            }
+        });
+        thread::spawn(move || {
+            let mut queued = Vec::new();
+            loop {
+                queued.clear();
+                outbound.drain_blocking(&mut queued);
+                let batch: Vec<u8> = queued.iter().map(|val| *val as u8).collect();
+                if master.write_all(&batch).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(PtyIO { path, _slave: slave })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl PtyIO {
+    pub fn open(_inbound: Arc<BlockingQueue<i32>>, _outbound: Arc<BlockingQueue<i32>>) -> io::Result<PtyIO> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "pty backend is only available on Linux"))
+    }
+}
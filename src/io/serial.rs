@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use crate::BlockingQueue;
+
+use super::port::IoDevice;
+
+/// `ttypkd` without waiting, `NO_DATA` unless two bytes are pending
+pub const PORT_TTYPKD_POLL: u8 = 0xfb;
+/// `ttyraw` without waiting, `NO_DATA` when no byte is pending
+pub const PORT_TTYRAW_POLL: u8 = 0xfc;
+/// line status, see `STATUS_OUTPUT_READY` and `STATUS_PENDING`
+pub const PORT_TTYSTAT: u8 = 0xfd;
+/// `ttyraw`, one byte per access
+pub const PORT_TTYRAW: u8 = 0xfe;
+/// `ttypkd`, two bytes per access packed high byte first
+pub const PORT_TTYPKD: u8 = 0xff;
+
+/// Ports the serial device answers, from `PORT_TTYPKD_POLL` through `PORT_TTYPKD`
+pub const SERIAL_PORTS: usize = (PORT_TTYPKD - PORT_TTYPKD_POLL) as usize + 1;
+
+/// Status bit set while the output queue has room for more
+pub const STATUS_OUTPUT_READY: i32 = 0x8000;
+/// Status bits holding the number of pending input bytes, saturated
+pub const STATUS_PENDING: i32 = 0x7fff;
+
+/// What a polling read returns when there is nothing to read, and a blocking read once the line is closed
+/// - a packed read of two 0xFF bytes looks the same, so check `PORT_TTYSTAT` first when that matters
+pub const NO_DATA: i32 = 0xffff;
+
+/// Output the backend hasn't taken yet beyond which the line reports itself busy
+const OUTPUT_HIGH_WATER: usize = 4096;
+
+/// The guest's side of the serial line, whichever backend serves the other side
+/// - `PORT_TTYRAW` and `PORT_TTYPKD` block until the backend has input or closes the line
+/// - the polling ports and `PORT_TTYSTAT` never block, for firmware written like it would be for a UART
+pub struct SerialPorts {
+    input: Arc<BlockingQueue<i32>>,
+    output: Arc<BlockingQueue<i32>>,
+}
+
+impl SerialPorts {
+    pub fn new(input: Arc<BlockingQueue<i32>>, output: Arc<BlockingQueue<i32>>) -> SerialPorts {
+        SerialPorts { input, output }
+    }
+
+    /// pending input count with `STATUS_OUTPUT_READY` or'ed in
+    fn status(&self) -> i32 {
+        let pending = self.input.len().min(STATUS_PENDING as usize) as i32;
+        match self.output.len() < OUTPUT_HIGH_WATER {
+            true => pending | STATUS_OUTPUT_READY,
+            false => pending,
+        }
+    }
+}
+
+/// Attached at `PORT_TTYPKD_POLL`, so ports are offsets from it
+impl IoDevice for SerialPorts {
+    fn read(&mut self, port: u8) -> i32 {
+        match port + PORT_TTYPKD_POLL {
+            PORT_TTYRAW => self.input.de_q_unless_closed().unwrap_or(NO_DATA),
+            PORT_TTYPKD => match (self.input.de_q_unless_closed(), self.input.de_q_unless_closed()) {
+                (Some(high), Some(low)) => (high << 8) | low,
+                _ => NO_DATA,
+            },
+            PORT_TTYRAW_POLL => self.input.try_de_q().unwrap_or(NO_DATA),
+            // the CPU is the only reader, so two pending bytes can't be taken from under it
+            PORT_TTYPKD_POLL if self.input.len() >= 2 => (self.input.de_q() << 8) | self.input.de_q(),
+            PORT_TTYPKD_POLL => NO_DATA,
+            _ => self.status(),
+        }
+    }
+
+    /// Writes never block, the polling ports write like their blocking counterparts
+    fn write(&mut self, port: u8, val: i32) {
+        match port + PORT_TTYPKD_POLL {
+            PORT_TTYRAW | PORT_TTYRAW_POLL => self.output.en_q(val & 255),
+            PORT_TTYPKD | PORT_TTYPKD_POLL => {
+                self.output.en_q(val >> 8);
+                if val & 255 != 0 {
+                    self.output.en_q(val);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Only the status can be known without consuming input
+    fn peek(&self, port: u8) -> Option<i32> {
+        match port + PORT_TTYPKD_POLL {
+            PORT_TTYSTAT => Some(self.status()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn closing_the_line_wakes_a_blocked_packed_read() {
+        let input = Arc::new(BlockingQueue::new());
+        let mut serial = SerialPorts::new(input.clone(), Arc::new(BlockingQueue::new()));
+        input.en_q(b'a' as i32);
+        let closer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            input.close();
+        });
+        assert_eq!(serial.read(PORT_TTYPKD - PORT_TTYPKD_POLL), NO_DATA);
+        assert_eq!(serial.read(PORT_TTYRAW - PORT_TTYPKD_POLL), NO_DATA);
+        closer.join().unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+use std::{collections::VecDeque, fmt, fs, io::{self, Read, Write}, net::{Shutdown, SocketAddr, TcpListener, TcpStream}, os::unix::{fs::FileTypeExt, net::{UnixListener, UnixStream}}, path::{Path, PathBuf}, sync::{Arc, Mutex}, thread, time::Duration};
+
+use crate::BlockingQueue;
+
+use super::telnet::{TelnetParser, IAC, NEGOTIATION};
+
+/// a client that stops reading for this long is dropped rather than stalling the others
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// most output kept while no interactive client is attached, older bytes are dropped first
+const BACKLOG_LIMIT: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What is spoken over a connection
+pub enum Protocol {
+    /// option negotiation on connect, IAC commands stripped from input and 0xFF escaped in output
+    Telnet,
+    /// plain bytes both ways
+    Raw,
+}
+
+/// Where a server accepts connections
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    pub fn tcp(addr: SocketAddr) -> io::Result<Listener> {
+        Ok(Listener::Tcp(TcpListener::bind(addr)?))
+    }
+
+    /// Listen on a Unix socket at `path`, replacing a stale socket left there by an earlier run
+    /// - any other kind of file at `path` is left alone and reported as in use
+    pub fn unix(path: &Path) -> io::Result<Listener> {
+        if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            fs::remove_file(path)?;
+        }
+        Ok(Listener::Unix(UnixListener::bind(path)?, path.to_path_buf()))
+    }
+
+    fn accept(&self) -> io::Result<(Stream, String)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, addr) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                Ok((Stream::Tcp(stream), addr.to_string()))
+            }
+            Listener::Unix(listener, path) => Ok((Stream::Unix(listener.accept()?.0), path.display().to_string())),
+        }
+    }
+}
+
+/// one connection, over TCP or a Unix socket
+enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(stream) => stream.try_clone().map(Stream::Tcp),
+            Stream::Unix(stream) => stream.try_clone().map(Stream::Unix),
+        }
+    }
+
+    fn set_write_timeout(&self, timeout: Duration) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_write_timeout(Some(timeout)),
+            Stream::Unix(stream) => stream.set_write_timeout(Some(timeout)),
+        }
+    }
+
+    fn shutdown(&self) {
+        let _ = match self {
+            Stream::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            Stream::Unix(stream) => stream.shutdown(Shutdown::Both),
+        };
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    /// its input goes to the guest
+    Interactive,
+    /// only watches the output, its input is dropped
+    Observer,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Interactive => write!(f, "client"),
+            Role::Observer => write!(f, "observer"),
+        }
+    }
+}
+
+/// write half of a connected client, `id` tells its disconnect apart from a later client's
+struct Client {
+    id: u64,
+    stream: Stream,
+}
+
+#[derive(Default)]
+/// Who is attached, and the output produced while nobody interactive was
+struct Clients {
+    interactive: Option<Client>,
+    observer: Option<Client>,
+    backlog: VecDeque<u8>,
+    next_id: u64,
+}
+
+impl Clients {
+    fn slot(&mut self, role: Role) -> &mut Option<Client> {
+        match role {
+            Role::Interactive => &mut self.interactive,
+            Role::Observer => &mut self.observer,
+        }
+    }
+
+    /// end the connection in `role`, its reader thread then sees the client leave
+    fn close(&mut self, role: Role) {
+        if let Some(client) = self.slot(role).take() {
+            client.stream.shutdown();
+        }
+    }
+
+    /// send output to whoever is attached, keeping it for later when the interactive client is missing
+    fn send(&mut self, bytes: &[u8]) {
+        if let Some(observer) = &mut self.observer {
+            if observer.stream.write_all(bytes).is_err() {
+                self.close(Role::Observer);
+            }
+        }
+        let sent = self.interactive.as_mut().is_some_and(|client| client.stream.write_all(bytes).is_ok());
+        if !sent {
+            self.close(Role::Interactive);
+            self.backlog.extend(bytes);
+            let excess = self.backlog.len().saturating_sub(BACKLOG_LIMIT);
+            self.backlog.drain(..excess);
+        }
+    }
+}
+
+/// Socket server connecting clients to the guest's serial ports
+/// - one interactive client at a time, whose input the guest reads, plus optionally a read-only observer
+/// - clients may leave and reconnect, output produced in between is replayed to the next interactive client
+/// - every client's input is handled on its own thread while guest output streams out on another
+pub struct SerialServer {
+    inbound: Arc<BlockingQueue<i32>>, // user input from the client -> cpu
+    outbound: Arc<BlockingQueue<i32>>, // cpu -> client console
+    listener: Listener,
+    protocol: Protocol,
+    allow_observer: bool,
+    clients: Arc<Mutex<Clients>>,
+}
+
+impl SerialServer {
+    /// Serve on `listener`, clients are only accepted once `serve` runs
+    pub fn new(listener: Listener, protocol: Protocol, inbound: Arc<BlockingQueue<i32>>, outbound: Arc<BlockingQueue<i32>>) -> SerialServer {
+        SerialServer { inbound, outbound, listener, protocol, allow_observer: false, clients: Arc::default() }
+    }
+
+    /// Accept a second, read-only client alongside the interactive one
+    pub fn allow_observer(&mut self, allow: bool) {
+        self.allow_observer = allow;
+    }
+
+    /// Serve clients for the rest of the run
+    pub fn serve(&mut self) {
+        let outbound = self.outbound.clone();
+        let clients = self.clients.clone();
+        let protocol = self.protocol;
+        thread::spawn(move || distribute(&outbound, &clients, protocol));
+        loop {
+            let result = self.listener.accept().and_then(|(client, peer)| self.attach(client, peer));
+            if let Err(err) = result {
+                println!("[WARN] Serial connection failed: {}", err);
+            }
+        }
+    }
+
+    /// give a new connection the interactive or observer role, or turn it away when both are taken
+    fn attach(&self, mut client: Stream, peer: String) -> io::Result<()> {
+        client.set_write_timeout(WRITE_TIMEOUT)?;
+        let mut clients = self.clients.lock().unwrap();
+        let role = if clients.interactive.is_none() {
+            Role::Interactive
+        } else if self.allow_observer && clients.observer.is_none() {
+            Role::Observer
+        } else {
+            println!("[INFO] Serial connection from {} refused, serial port busy", peer);
+            return client.write_all(b"serial port busy\r\n");
+        };
+        let mut stream = client.try_clone()?;
+        if self.protocol == Protocol::Telnet {
+            stream.write_all(&NEGOTIATION)?;
+        }
+        if role == Role::Interactive {
+            stream.write_all(clients.backlog.make_contiguous())?;
+            clients.backlog.clear();
+        }
+        let id = clients.next_id;
+        clients.next_id += 1;
+        *clients.slot(role) = Some(Client { id, stream });
+        drop(clients);
+        println!("[INFO] Serial {} connected from {}", role, peer);
+        let inbound = (role == Role::Interactive).then(|| self.inbound.clone());
+        let clients = self.clients.clone();
+        let mut parser = (self.protocol == Protocol::Telnet).then(TelnetParser::new);
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok(len @ 1..) = client.read(&mut buf) {
+                for byte in &buf[..len] {
+                    let byte = match &mut parser {
+                        Some(parser) => parser.feed(*byte),
+                        None => Some(*byte),
+                    };
+                    if let (Some(byte), Some(inbound)) = (byte, &inbound) {
+                        inbound.en_q(byte as i32);
+                    }
+                }
+            }
+            let mut clients = clients.lock().unwrap();
+            if clients.slot(role).as_ref().is_some_and(|c| c.id == id) {
+                clients.close(role);
+            }
+            println!("[INFO] Serial {} {} disconnected", role, peer);
+        });
+        Ok(())
+    }
+}
+
+/// move guest output to the clients in batches, telnet escapes 0xFF as `IAC IAC`
+fn distribute(outbound: &BlockingQueue<i32>, clients: &Mutex<Clients>, protocol: Protocol) {
+    let (mut queued, mut batch) = (Vec::new(), Vec::new());
+    loop {
+        queued.clear();
+        batch.clear();
+        outbound.drain_blocking(&mut queued);
+        for val in &queued {
+            batch.push(*val as u8);
+            if protocol == Protocol::Telnet && *val as u8 == IAC {
+                batch.push(IAC);
+            }
+        }
+        clients.lock().unwrap().send(&batch);
+    }
+}
@@ -0,0 +1,109 @@
+pub const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const OPT_ECHO: u8 = 1;
+const OPT_SGA: u8 = 3;
+
+/// sent on connect: the client should suppress go-ahead and leave echoing to the guest
+pub const NEGOTIATION: [u8; 6] = [IAC, DO, OPT_SGA, IAC, DONT, OPT_ECHO];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseState {
+    Data,
+    /// after a CR, a following LF or NUL belongs to it
+    Cr,
+    Iac,
+    /// after WILL, WONT, DO or DONT, the next byte is the option
+    Option,
+    Sub,
+    SubIac,
+}
+
+/// Strips telnet commands and option negotiation out of the client's byte stream
+/// - `IAC IAC` is a literal 0xFF
+/// - `CR LF` and `CR NUL` both arrive at the guest as a single CR, like a terminal's enter key
+#[derive(Debug)]
+pub struct TelnetParser {
+    state: ParseState,
+}
+
+impl TelnetParser {
+    pub fn new() -> TelnetParser {
+        TelnetParser { state: ParseState::Data }
+    }
+
+    /// Feed one byte from the client, returns it if the guest should see it
+    pub fn feed(&mut self, byte: u8) -> Option<u8> {
+        let (state, out) = match (self.state, byte) {
+            (ParseState::Data | ParseState::Cr, IAC) => (ParseState::Iac, None),
+            (ParseState::Cr, b'\n' | 0) => (ParseState::Data, None),
+            (ParseState::Data | ParseState::Cr, b'\r') => (ParseState::Cr, Some(byte)),
+            (ParseState::Data | ParseState::Cr, _) => (ParseState::Data, Some(byte)),
+            (ParseState::Iac, IAC) => (ParseState::Data, Some(IAC)),
+            (ParseState::Iac, WILL | WONT | DO | DONT) => (ParseState::Option, None),
+            (ParseState::Iac, SB) => (ParseState::Sub, None),
+            (ParseState::Iac, _) | (ParseState::Option, _) => (ParseState::Data, None),
+            (ParseState::Sub, IAC) => (ParseState::SubIac, None),
+            (ParseState::Sub, _) => (ParseState::Sub, None),
+            (ParseState::SubIac, SE) => (ParseState::Data, None),
+            (ParseState::SubIac, _) => (ParseState::Sub, None),
+        };
+        self.state = state;
+        out
+    }
+}
+
+impl Default for TelnetParser {
+    fn default() -> TelnetParser {
+        TelnetParser::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<u8> {
+        let mut parser = TelnetParser::new();
+        bytes.iter().filter_map(|b| parser.feed(*b)).collect()
+    }
+
+    #[test]
+    fn doubled_iac_is_a_literal_ff() {
+        assert_eq!(parse(&[b'a', IAC, IAC, b'b']), vec![b'a', 0xff, b'b']);
+    }
+
+    #[test]
+    fn option_negotiation_is_stripped() {
+        let input = [b'a', IAC, WILL, OPT_ECHO, IAC, DO, OPT_SGA, IAC, WONT, OPT_ECHO, IAC, DONT, OPT_SGA, b'b'];
+        assert_eq!(parse(&input), vec![b'a', b'b']);
+    }
+
+    #[test]
+    fn other_commands_are_stripped() {
+        assert_eq!(parse(&[b'a', IAC, 241, b'b']), vec![b'a', b'b']);
+    }
+
+    #[test]
+    fn subnegotiation_is_stripped_up_to_iac_se() {
+        let input = [b'a', IAC, SB, 24, 0, b'x', IAC, IAC, SE, b'y', IAC, SE, b'b'];
+        assert_eq!(parse(&input), vec![b'a', b'b']);
+    }
+
+    #[test]
+    fn cr_lf_and_cr_nul_fold_to_cr() {
+        assert_eq!(parse(b"a\r\nb\r\0c"), b"a\rb\rc".to_vec());
+        assert_eq!(parse(b"\r\r\n"), b"\r\r".to_vec());
+        assert_eq!(parse(b"\n\0"), b"\n\0".to_vec());
+    }
+
+    #[test]
+    fn iac_right_after_cr_is_still_parsed() {
+        assert_eq!(parse(&[b'\r', IAC, IAC]), vec![b'\r', 0xff]);
+        assert_eq!(parse(&[b'\r', IAC, DO, OPT_ECHO, b'x']), vec![b'\r', b'x']);
+    }
+}
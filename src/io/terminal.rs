@@ -0,0 +1,94 @@
+use std::{io::{self, IsTerminal, Read, Write}, process::{Command, Stdio}, sync::{atomic::{AtomicBool, Ordering}, Arc}, thread};
+
+use crate::BlockingQueue;
+
+/// Ctrl-], the same escape telnet clients use
+pub const DEFAULT_ESCAPE: u8 = 0x1d;
+
+/// How an escape key is shown to the user, `Ctrl-]` for control characters
+pub fn key_name(key: u8) -> String {
+    match key {
+        0..=0x1f => format!("Ctrl-{}", (key ^ 0x40) as char),
+        _ => (key as char).to_string(),
+    }
+}
+
+/// Host terminal switched to raw mode, the previous settings come back when this is dropped
+pub struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["raw", "-echo"])?;
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// `stty` acts on the terminal it gets as stdin, so that has to be ours rather than a pipe
+fn stty(args: &[&str]) -> io::Result<String> {
+    let out = Command::new("stty").args(args).stdin(Stdio::inherit()).stderr(Stdio::inherit()).output()?;
+    if !out.status.success() {
+        return Err(io::Error::other(format!("stty {} failed", args.join(" "))));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// The emulator's own stdin and stdout as the guest's serial terminal
+/// - keys go to the guest as typed, except `escape`, which hands control back to the host
+/// - on escape the input queue is closed, so a guest blocked reading input wakes up and the CPU can stop
+pub struct TerminalIO {
+    _raw: RawMode,
+    escaped: Arc<AtomicBool>,
+}
+
+impl TerminalIO {
+    /// Switch the terminal to raw mode and start moving bytes between it and the queues
+    pub fn start(inbound: Arc<BlockingQueue<i32>>, outbound: Arc<BlockingQueue<i32>>, escape: u8) -> io::Result<TerminalIO> {
+        if !io::stdin().is_terminal() {
+            return Err(io::Error::other("stdin is not a terminal"));
+        }
+        let raw = RawMode::enable()?;
+        let escaped = Arc::new(AtomicBool::new(false));
+        let flag = escaped.clone();
+        thread::spawn(move || {
+            let mut stdin = io::stdin().lock();
+            let mut buf = [0; 64];
+            while let Ok(len @ 1..) = stdin.read(&mut buf) {
+                for byte in &buf[..len] {
+                    if *byte == escape {
+                        flag.store(true, Ordering::Release);
+                        inbound.close();
+                        return;
+                    }
+                    inbound.en_q(*byte as i32);
+                }
+            }
+        });
+        thread::spawn(move || {
+            let mut queued = Vec::new();
+            loop {
+                queued.clear();
+                outbound.drain_blocking(&mut queued);
+                let batch: Vec<u8> = queued.iter().map(|val| *val as u8).collect();
+                let mut stdout = io::stdout().lock();
+                if stdout.write_all(&batch).and_then(|_| stdout.flush()).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(TerminalIO { _raw: raw, escaped })
+    }
+
+    /// Whether the escape key has been pressed
+    pub fn escaped(&self) -> bool {
+        self.escaped.load(Ordering::Acquire)
+    }
+}
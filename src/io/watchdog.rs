@@ -0,0 +1,79 @@
+use std::sync::{atomic::{AtomicU64, Ordering}, Arc};
+
+use super::port::IoDevice;
+
+/// `out` to this port kicks the watchdog, `inp` reads the instructions left before it expires
+pub const PORT_WATCHDOG: u8 = 0xf9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What the emulator does once the watchdog runs out
+pub enum WatchdogAction {
+    /// reset the CPU and keep running
+    Reset,
+    /// stop emulation with `StopReason::Watchdog`
+    Stop,
+}
+
+#[derive(Debug)]
+/// Watchdog timer counting down once per executed instruction
+/// - disabled unless the host gives it a timeout
+/// - the guest reloads it by writing any value to `PORT_WATCHDOG`
+/// - the countdown is atomic so the CPU ticking it and the port device kicking it can share one watchdog
+pub struct Watchdog {
+    timeout: u64,
+    remaining: AtomicU64,
+    action: WatchdogAction,
+}
+
+impl Watchdog {
+    pub fn disabled() -> Watchdog {
+        Watchdog { timeout: 0, remaining: AtomicU64::new(0), action: WatchdogAction::Stop }
+    }
+
+    pub fn new(timeout: u64, action: WatchdogAction) -> Watchdog {
+        Watchdog { timeout, remaining: AtomicU64::new(timeout), action }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.timeout != 0
+    }
+
+    /// reload the countdown with the full timeout
+    pub fn kick(&self) {
+        self.remaining.store(self.timeout, Ordering::Relaxed);
+    }
+
+    /// instructions left, saturated to 16 bits for the guest
+    pub fn remaining(&self) -> i32 {
+        self.remaining.load(Ordering::Relaxed).min(0xffff) as i32
+    }
+
+    /// advance by one instruction
+    /// - returns the configured action when the countdown hits zero, then reloads
+    pub fn tick(&self) -> Option<WatchdogAction> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let remaining = self.remaining.load(Ordering::Relaxed) - 1;
+        self.remaining.store(remaining, Ordering::Relaxed);
+        if remaining == 0 {
+            self.kick();
+            return Some(self.action);
+        }
+        None
+    }
+}
+
+impl IoDevice for Arc<Watchdog> {
+    fn read(&mut self, _port: u8) -> i32 {
+        self.remaining()
+    }
+
+    fn write(&mut self, _port: u8, _val: i32) {
+        self.kick();
+    }
+
+    fn peek(&self, _port: u8) -> Option<i32> {
+        Some(self.remaining())
+    }
+}
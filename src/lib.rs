@@ -1,5 +1,11 @@
 pub mod cpu;
+pub mod diff;
+pub mod export;
 pub mod io;
+pub mod loader;
+mod route;
+pub mod sourcemap;
+pub mod symbols;
 
 pub const MAGIC_NUMBER: i32 = u16::MAX as i32;
 
@@ -8,9 +14,11 @@ use std::collections::*;
 
 #[derive(Debug)]
 /// Thread-safe queue that blocks de_q on empty
+/// - a producer that is done can `close` it to wake consumers waiting in `de_q_unless_closed`
 pub struct BlockingQueue<T> {
     q: Mutex<VecDeque<T>>,
     cv: Condvar,
+    closed: atomic::AtomicBool,
 }
 impl<T> BlockingQueue<T> {
     /// Create empty blocking queue
@@ -18,6 +26,7 @@ impl<T> BlockingQueue<T> {
         Self {
             q: Mutex::new(VecDeque::new()),
             cv: Condvar::new(),
+            closed: atomic::AtomicBool::new(false),
         }
     }
     /// push input on back of queue
@@ -32,13 +41,51 @@ impl<T> BlockingQueue<T> {
     /// - same for condition variable
     pub fn de_q(&self) -> T {
         let mut lq = self.q.lock().unwrap();
-        while lq.len() == 0 {
+        while lq.is_empty() {
             lq = self.cv.wait(lq).unwrap();
         }
         lq.pop_front().unwrap()
     }
+    /// pop element from front of queue, or None once the queue is closed and drained
+    pub fn de_q_unless_closed(&self) -> Option<T> {
+        let mut lq = self.q.lock().unwrap();
+        while lq.is_empty() && !self.is_closed() {
+            lq = self.cv.wait(lq).unwrap();
+        }
+        lq.pop_front()
+    }
+    /// no more elements will come, wakes every consumer waiting in `de_q_unless_closed`
+    pub fn close(&self) {
+        let _lq = self.q.lock().unwrap();
+        self.closed.store(true, atomic::Ordering::Release);
+        self.cv.notify_all();
+    }
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(atomic::Ordering::Acquire)
+    }
+    /// wait for at least one element, then move everything queued onto the end of `batch`
+    pub fn drain_blocking(&self, batch: &mut Vec<T>) {
+        let mut lq = self.q.lock().unwrap();
+        while lq.is_empty() {
+            lq = self.cv.wait(lq).unwrap();
+        }
+        batch.extend(lq.drain(..));
+    }
+    /// pop element from front of queue if there is one, never blocks
+    pub fn try_de_q(&self) -> Option<T> {
+        self.q.lock().unwrap().pop_front()
+    }
     /// return number of elements in queue
     pub fn len(&self) -> usize {
         self.q.lock().unwrap().len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.q.lock().unwrap().is_empty()
+    }
+}
+
+impl<T> Default for BlockingQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,615 @@
+use std::{fmt, io::{self, Read}, path::{Path, PathBuf}};
+
+use crate::MAGIC_NUMBER;
+
+/// Largest image that fits the 16 bit address space
+pub const MAX_WORDS: usize = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Program image formats the loader understands
+pub enum ImageFormat {
+    /// Logisim `v2.0 raw`, customasm `logisim16`
+    LogisimRaw,
+    /// big-endian 16 bit words, customasm `binary`
+    Binary,
+    /// customasm `intelhex`, byte addressed with each word stored big-endian
+    IntelHex,
+    /// one long string of hex digits, customasm `hexstr`
+    HexStr,
+    /// one long string of binary digits, customasm `binstr`
+    BinStr,
+}
+
+impl ImageFormat {
+    /// Look a format up by its customasm `--format` name or a short alias
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name {
+            "logisim" | "logisim16" => Some(ImageFormat::LogisimRaw),
+            "bin" | "binary" => Some(ImageFormat::Binary),
+            "ihex" | "intelhex" => Some(ImageFormat::IntelHex),
+            "hexstr" => Some(ImageFormat::HexStr),
+            "binstr" => Some(ImageFormat::BinStr),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the file content
+    /// - text formats are recognised by their header or alphabet, anything else is raw binary
+    /// - any `... raw` header counts as Logisim so a wrong version is reported rather than loaded as binary
+    /// - digits that are all 0 and 1 are `binstr` unless their count only fits whole `hexstr` words,
+    ///   so a `hexstr` image of 0s and 1s with a multiple of 16 digits needs its format given explicitly
+    pub fn detect(content: &[u8]) -> ImageFormat {
+        let text = match std::str::from_utf8(content) {
+            Ok(text) => text.trim(),
+            Err(_) => return ImageFormat::Binary,
+        };
+        if text.is_empty() {
+            ImageFormat::Binary
+        } else if text.lines().next().is_some_and(|header| header.trim_end().ends_with(" raw")) {
+            ImageFormat::LogisimRaw
+        } else if text.lines().all(|l| l.trim().is_empty() || l.trim_start().starts_with(':')) {
+            ImageFormat::IntelHex
+        } else if text.chars().all(|c| c == '0' || c == '1' || c.is_whitespace()) {
+            let digits = text.chars().filter(|c| !c.is_whitespace()).count();
+            match digits % 16 != 0 && digits % 4 == 0 {
+                true => ImageFormat::HexStr,
+                false => ImageFormat::BinStr,
+            }
+        } else if text.chars().all(|c| c.is_ascii_hexdigit() || c.is_whitespace()) {
+            ImageFormat::HexStr
+        } else {
+            ImageFormat::Binary
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How the loader treats problems it can recover from
+pub enum LoadMode {
+    /// any warning fails the load
+    Strict,
+    /// warnings are printed and loading carries on
+    Lenient,
+}
+
+#[derive(Debug)]
+/// What went wrong while loading an image
+pub enum LoadErrorKind {
+    /// the file could not be read
+    Io(io::Error),
+    /// a Logisim image without the `v2.0 raw` header
+    BadHeader,
+    /// a token that is not a valid word or run
+    InvalidToken,
+    /// a word wider than 16 bits
+    OutOfRange,
+    /// the image ends in the middle of a word
+    PartialWord,
+    /// the image does not fit the 16 bit address space
+    Overflow,
+    /// an Intel HEX line that is not a well formed record
+    BadRecord,
+    /// an Intel HEX record whose checksum does not add up
+    BadChecksum,
+    /// an Intel HEX record type the loader does not handle
+    UnsupportedRecord,
+    /// the image lands on words already claimed by the named image
+    Overlap(PathBuf),
+    /// a symbol file line that is not `name = value`
+    BadSymbol,
+    /// text in none of the known image formats, loaded as raw binary
+    UndetectedFormat,
+}
+
+impl fmt::Display for LoadErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadErrorKind::Io(err) => write!(f, "{}", err),
+            LoadErrorKind::BadHeader => write!(f, "missing `v2.0 raw` header"),
+            LoadErrorKind::InvalidToken => write!(f, "invalid word"),
+            LoadErrorKind::OutOfRange => write!(f, "value wider than 16 bits"),
+            LoadErrorKind::PartialWord => write!(f, "image ends in a partial word"),
+            LoadErrorKind::Overflow => write!(f, "image overflows {} words", MAX_WORDS),
+            LoadErrorKind::BadRecord => write!(f, "malformed Intel HEX record"),
+            LoadErrorKind::BadChecksum => write!(f, "Intel HEX checksum mismatch"),
+            LoadErrorKind::UnsupportedRecord => write!(f, "unsupported Intel HEX record type"),
+            LoadErrorKind::Overlap(other) => write!(f, "image overlaps {}", other.display()),
+            LoadErrorKind::BadSymbol => write!(f, "malformed symbol definition"),
+            LoadErrorKind::UndetectedFormat => write!(f, "text in no known image format, give --format binary to load it as words"),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Loader error pointing at the offending token
+/// - `line` and `column` are 1-based, 0 when the error is not tied to a position
+/// - binary images count bytes in `column` on line 1
+pub struct LoadError {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub kind: LoadErrorKind,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}", file.display())?,
+            None => write!(f, "<buffer>")?,
+        }
+        if self.line != 0 {
+            write!(f, ":{}:{}", self.line, self.column)?;
+        }
+        write!(f, ": {}", self.kind)?;
+        if !self.token.is_empty() {
+            write!(f, " `{}`", self.token)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            LoadErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// One image and the address its first word is placed at
+/// - `read_only` images are write protected once loaded
+pub struct ImageSpec {
+    pub file: PathBuf,
+    pub base: u16,
+    pub format: Option<ImageFormat>,
+    pub read_only: bool,
+}
+
+impl ImageSpec {
+    pub fn new(file: impl Into<PathBuf>, base: u16) -> ImageSpec {
+        ImageSpec { file: file.into(), base, format: None, read_only: false }
+    }
+}
+
+/// Shared state of one load, decides whether a problem is fatal
+pub(crate) struct Context<'a> {
+    pub(crate) file: Option<&'a Path>,
+    pub(crate) mode: LoadMode,
+}
+
+impl Context<'_> {
+    pub(crate) fn error(&self, line: usize, column: usize, token: &str, kind: LoadErrorKind) -> LoadError {
+        LoadError { file: self.file.map(Path::to_path_buf), line, column, token: token.to_string(), kind }
+    }
+
+    /// report a recoverable problem
+    /// - lenient mode prints it and carries on, strict mode fails the load
+    pub(crate) fn warn(&self, line: usize, column: usize, token: &str, kind: LoadErrorKind) -> Result<(), LoadError> {
+        let err = self.error(line, column, token, kind);
+        match self.mode {
+            LoadMode::Strict => Err(err),
+            LoadMode::Lenient => {
+                println!("[WARN] {}", err);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Read an image file into words starting at address 0
+/// - `format` of None detects the format from the content
+/// - a file named `-` is read from stdin
+pub fn load_file(file: &Path, format: Option<ImageFormat>, mode: LoadMode) -> Result<Vec<i32>, LoadError> {
+    if file == Path::new("-") {
+        return load_reader(&mut io::stdin().lock(), Some(file), format, mode);
+    }
+    let ctx = Context { file: Some(file), mode };
+    let content = std::fs::read(file).map_err(|err| ctx.error(0, 0, "", LoadErrorKind::Io(err)))?;
+    let format = match format {
+        Some(format) => format,
+        None => detect_with(&ctx, &content)?,
+    };
+    parse_with(&ctx, &content, format)
+}
+
+/// Read an image from `reader` until it ends, `name` is only used to report errors
+pub fn load_reader(reader: &mut dyn Read, name: Option<&Path>, format: Option<ImageFormat>, mode: LoadMode) -> Result<Vec<i32>, LoadError> {
+    let ctx = Context { file: name, mode };
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content).map_err(|err| ctx.error(0, 0, "", LoadErrorKind::Io(err)))?;
+    let format = match format {
+        Some(format) => format,
+        None => detect_with(&ctx, &content)?,
+    };
+    parse_with(&ctx, &content, format)
+}
+
+/// detect the format of `content`, warning when text falls through to binary
+/// - that is most likely a source file or a format the loader doesn't know, not a memory image
+fn detect_with(ctx: &Context, content: &[u8]) -> Result<ImageFormat, LoadError> {
+    let format = ImageFormat::detect(content);
+    let text = std::str::from_utf8(content).is_ok_and(|text| text.chars().all(|c| !c.is_control() || c.is_whitespace()));
+    if format == ImageFormat::Binary && text && !content.is_empty() {
+        ctx.warn(0, 0, "", LoadErrorKind::UndetectedFormat)?;
+    }
+    Ok(format)
+}
+
+/// Read every image and check they fit side by side in the address space
+/// - returns each image's base address and words, in the order given
+/// - an image running past 0xFFFF is an overflow, one touching an earlier image an overlap
+pub fn load_images(images: &[ImageSpec], mode: LoadMode) -> Result<Vec<(u16, Vec<i32>)>, LoadError> {
+    let mut loaded: Vec<(u16, Vec<i32>)> = Vec::with_capacity(images.len());
+    for spec in images {
+        let words = load_file(&spec.file, spec.format, mode)?;
+        let ctx = Context { file: Some(&spec.file), mode };
+        let start = spec.base as usize;
+        let end = start + words.len();
+        let span = format!("{:#06x}-{:#06x}", start, end.saturating_sub(1));
+        if end > MAX_WORDS {
+            return Err(ctx.error(0, 0, &span, LoadErrorKind::Overflow));
+        }
+        let clash = loaded.iter().zip(images).find(|((base, other), _)| {
+            let other_start = *base as usize;
+            !words.is_empty() && start < other_start + other.len() && other_start < end
+        });
+        if let Some((_, other)) = clash {
+            return Err(ctx.error(0, 0, &span, LoadErrorKind::Overlap(other.file.clone())));
+        }
+        loaded.push((spec.base, words));
+    }
+    Ok(loaded)
+}
+
+/// Decode an image already in memory
+pub fn parse(content: &[u8], format: ImageFormat, mode: LoadMode) -> Result<Vec<i32>, LoadError> {
+    parse_with(&Context { file: None, mode }, content, format)
+}
+
+fn parse_with(ctx: &Context, content: &[u8], format: ImageFormat) -> Result<Vec<i32>, LoadError> {
+    match format {
+        ImageFormat::LogisimRaw => parse_logisim(ctx, &String::from_utf8_lossy(content)),
+        ImageFormat::Binary => parse_binary(ctx, content),
+        ImageFormat::IntelHex => parse_intel_hex(ctx, &String::from_utf8_lossy(content)),
+        ImageFormat::HexStr => parse_digits(ctx, &String::from_utf8_lossy(content), 16, 4),
+        ImageFormat::BinStr => parse_digits(ctx, &String::from_utf8_lossy(content), 2, 16),
+    }
+}
+
+/// whitespace separated tokens of a line with their 1-based column
+fn tokens(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    line.split_whitespace().map(move |token| (token.as_ptr() as usize - line.as_ptr() as usize + 1, token))
+}
+
+/// Logisim `v2.0 raw` images are whitespace separated hex words after the header
+/// - `N*V` is a run of N (decimal) copies of the hex word V
+/// - `#` starts a comment running to the end of the line
+fn parse_logisim(ctx: &Context, content: &str) -> Result<Vec<i32>, LoadError> {
+    let mut lines = content.lines();
+    let header = lines.next().unwrap_or("");
+    if header.trim_end() != "v2.0 raw" {
+        return Err(ctx.error(1, 1, header, LoadErrorKind::BadHeader));
+    }
+    let mut words: Vec<i32> = Vec::new();
+    for (num, line) in lines.enumerate().map(|(num, line)| (num + 2, line)) {
+        for (col, token) in tokens(line.split('#').next().unwrap_or("")) {
+            let (count, val) = match token.split_once('*') {
+                // a count too big for usize can't fit the address space either
+                Some((count, val)) if !count.is_empty() && count.bytes().all(|b| b.is_ascii_digit()) => (Some(count.parse::<usize>().unwrap_or(usize::MAX)), val),
+                Some((_, val)) => (None, val),
+                None => (Some(1), token),
+            };
+            let (count, mut val) = match (count, i32::from_str_radix(val, 16)) {
+                (Some(count), Ok(val)) => (count, val),
+                _ => {
+                    ctx.warn(num, col, token, LoadErrorKind::InvalidToken)?;
+                    (1, 0)
+                }
+            };
+            if val & MAGIC_NUMBER != val {
+                ctx.warn(num, col, token, LoadErrorKind::OutOfRange)?;
+                val &= MAGIC_NUMBER;
+            }
+            if count > MAX_WORDS - words.len() {
+                return Err(ctx.error(num, col, token, LoadErrorKind::Overflow));
+            }
+            words.resize(words.len() + count, val);
+        }
+    }
+    Ok(words)
+}
+
+fn parse_binary(ctx: &Context, content: &[u8]) -> Result<Vec<i32>, LoadError> {
+    if content.len() > MAX_WORDS * 2 {
+        return Err(ctx.error(1, MAX_WORDS * 2 + 1, "", LoadErrorKind::Overflow));
+    }
+    if !content.len().is_multiple_of(2) {
+        let last = content.len() - 1;
+        ctx.warn(1, last + 1, &format!("{:02x}", content[last]), LoadErrorKind::PartialWord)?;
+    }
+    Ok(content
+        .chunks(2)
+        .map(|pair| (pair[0] as i32) << 8 | *pair.get(1).unwrap_or(&0) as i32)
+        .collect())
+}
+
+/// `hexstr` and `binstr` images are one run of digits, `digits` of them per word
+fn parse_digits(ctx: &Context, content: &str, radix: u32, digits: usize) -> Result<Vec<i32>, LoadError> {
+    let clean = content
+        .lines()
+        .enumerate()
+        .flat_map(|(num, line)| line.chars().enumerate().map(move |(col, c)| (num + 1, col + 1, c)))
+        .filter(|(_, _, c)| !c.is_whitespace())
+        .collect::<Vec<(usize, usize, char)>>();
+    if clean.len() > MAX_WORDS * digits {
+        let (num, col, _) = clean[MAX_WORDS * digits];
+        return Err(ctx.error(num, col, "", LoadErrorKind::Overflow));
+    }
+    let mut words = Vec::with_capacity(clean.len() / digits + 1);
+    for chunk in clean.chunks(digits) {
+        let (num, col, _) = chunk[0];
+        let word = chunk.iter().map(|(_, _, c)| c).collect::<String>();
+        if chunk.len() != digits {
+            ctx.warn(num, col, &word, LoadErrorKind::PartialWord)?;
+        }
+        let padded = format!("{:0<width$}", word, width = digits);
+        words.push(match i32::from_str_radix(&padded, radix) {
+            Ok(val) => val,
+            Err(_) => {
+                ctx.warn(num, col, &word, LoadErrorKind::InvalidToken)?;
+                0
+            }
+        });
+    }
+    Ok(words)
+}
+
+fn parse_intel_hex(ctx: &Context, content: &str) -> Result<Vec<i32>, LoadError> {
+    let mut words: Vec<i32> = Vec::new();
+    let mut upper: usize = 0;
+    for (num, line) in content.lines().enumerate().map(|(num, line)| (num + 1, line)) {
+        let (col, record) = match tokens(line).next() {
+            Some(token) => token,
+            None => continue,
+        };
+        let bytes = record
+            .strip_prefix(':')
+            .filter(|rec| rec.len().is_multiple_of(2))
+            .and_then(|rec| (0..rec.len()).step_by(2).map(|i| u8::from_str_radix(rec.get(i..i + 2)?, 16).ok()).collect::<Option<Vec<u8>>>());
+        let bytes = match bytes {
+            Some(bytes) if bytes.len() >= 5 && bytes.len() == bytes[0] as usize + 5 => bytes,
+            _ => {
+                ctx.warn(num, col, record, LoadErrorKind::BadRecord)?;
+                continue;
+            }
+        };
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            ctx.warn(num, col, record, LoadErrorKind::BadChecksum)?;
+        }
+        let data = &bytes[4..bytes.len() - 1];
+        let offset = (bytes[1] as usize) << 8 | bytes[2] as usize;
+        match bytes[3] {
+            0x00 => {
+                for (i, byte) in data.iter().enumerate() {
+                    let addr = upper + offset + i;
+                    if addr / 2 >= MAX_WORDS {
+                        return Err(ctx.error(num, col, record, LoadErrorKind::Overflow));
+                    }
+                    if words.len() <= addr / 2 {
+                        words.resize(addr / 2 + 1, 0);
+                    }
+                    let shift = if addr.is_multiple_of(2) { 8 } else { 0 };
+                    words[addr / 2] = (words[addr / 2] & !(0xff << shift)) | (*byte as i32) << shift;
+                }
+            }
+            0x01 => break,
+            0x02 if data.len() == 2 => upper = ((data[0] as usize) << 8 | data[1] as usize) << 4,
+            0x04 if data.len() == 2 => upper = ((data[0] as usize) << 8 | data[1] as usize) << 16,
+            0x03 | 0x05 => (),
+            _ => ctx.warn(num, col, record, LoadErrorKind::UnsupportedRecord)?,
+        }
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an Intel HEX record with its checksum filled in
+    fn record(addr: u16, kind: u8, data: &[u8]) -> String {
+        let bytes: Vec<u8> = [data.len() as u8, (addr >> 8) as u8, addr as u8, kind].into_iter().chain(data.iter().copied()).collect();
+        let sum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        let hex: String = bytes.iter().chain(&[sum.wrapping_neg()]).map(|b| format!("{:02X}", b)).collect();
+        format!(":{}\n", hex)
+    }
+
+    fn ihex(records: &[String]) -> String {
+        records.concat() + &record(0, 0x01, &[])
+    }
+
+    #[test]
+    fn intel_hex_stores_words_big_endian() {
+        let image = ihex(&[record(0, 0x00, &[0x12, 0x34, 0xab, 0xcd])]);
+        assert_eq!(parse(image.as_bytes(), ImageFormat::IntelHex, LoadMode::Strict).unwrap(), vec![0x1234, 0xabcd]);
+    }
+
+    #[test]
+    fn intel_hex_odd_byte_addresses_fill_low_halves() {
+        let image = ihex(&[record(3, 0x00, &[0x56]), record(1, 0x00, &[0xab, 0x12])]);
+        assert_eq!(parse(image.as_bytes(), ImageFormat::IntelHex, LoadMode::Strict).unwrap(), vec![0x00ab, 0x1256]);
+    }
+
+    #[test]
+    fn intel_hex_segment_and_linear_bases() {
+        let image = ihex(&[record(0, 0x02, &[0x00, 0x10]), record(0, 0x00, &[0x11, 0x22])]);
+        let words = parse(image.as_bytes(), ImageFormat::IntelHex, LoadMode::Strict).unwrap();
+        assert_eq!((words.len(), words[0x80]), (0x81, 0x1122));
+        let image = ihex(&[record(0, 0x04, &[0x00, 0x01]), record(2, 0x00, &[0x33, 0x44])]);
+        let words = parse(image.as_bytes(), ImageFormat::IntelHex, LoadMode::Strict).unwrap();
+        assert_eq!((words.len(), words[0x8001]), (0x8002, 0x3344));
+    }
+
+    #[test]
+    fn intel_hex_past_the_address_space_overflows() {
+        let image = ihex(&[record(0, 0x04, &[0x00, 0x02]), record(0, 0x00, &[0x00])]);
+        let err = parse(image.as_bytes(), ImageFormat::IntelHex, LoadMode::Lenient).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::Overflow));
+        assert_eq!((err.line, err.column), (2, 1));
+    }
+
+    #[test]
+    fn intel_hex_bad_checksum() {
+        let good = record(0, 0x00, &[0x12, 0x34]);
+        let bad = good.replace("1234", "1235");
+        let image = ihex(&[bad]);
+        let err = parse(image.as_bytes(), ImageFormat::IntelHex, LoadMode::Strict).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::BadChecksum));
+        assert_eq!(parse(image.as_bytes(), ImageFormat::IntelHex, LoadMode::Lenient).unwrap(), vec![0x1235]);
+    }
+
+    #[test]
+    fn hexstr_ignores_whitespace_and_pads_a_partial_word() {
+        assert_eq!(parse(b"1234ab\ncd 00ff\n", ImageFormat::HexStr, LoadMode::Strict).unwrap(), vec![0x1234, 0xabcd, 0x00ff]);
+        assert_eq!(parse(b"123412", ImageFormat::HexStr, LoadMode::Lenient).unwrap(), vec![0x1234, 0x1200]);
+        let err = parse(b"123412", ImageFormat::HexStr, LoadMode::Strict).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::PartialWord));
+        assert_eq!((err.line, err.column, err.token.as_str()), (1, 5, "12"));
+    }
+
+    #[test]
+    fn binstr_sixteen_digits_per_word() {
+        let image = b"0000000000000001\n1000000000000000";
+        assert_eq!(parse(image, ImageFormat::BinStr, LoadMode::Strict).unwrap(), vec![0x0001, 0x8000]);
+    }
+
+    #[test]
+    fn detect_formats() {
+        assert_eq!(ImageFormat::detect(b"v2.0 raw\n1234\n"), ImageFormat::LogisimRaw);
+        assert_eq!(ImageFormat::detect(ihex(&[]).as_bytes()), ImageFormat::IntelHex);
+        assert_eq!(ImageFormat::detect(b"12ab"), ImageFormat::HexStr);
+        assert_eq!(ImageFormat::detect(b"0000000000000001"), ImageFormat::BinStr);
+        assert_eq!(ImageFormat::detect(b"\x12\x34"), ImageFormat::Binary);
+    }
+
+    #[test]
+    fn undetected_text_is_binary_only_when_lenient() {
+        let source = b"main:\n    lsi r1, 0x41 ; print A\n";
+        let ctx = Context { file: None, mode: LoadMode::Strict };
+        assert!(matches!(detect_with(&ctx, source).unwrap_err().kind, LoadErrorKind::UndetectedFormat));
+        let ctx = Context { file: None, mode: LoadMode::Lenient };
+        assert_eq!(detect_with(&ctx, source).unwrap(), ImageFormat::Binary);
+        let ctx = Context { file: None, mode: LoadMode::Strict };
+        assert_eq!(detect_with(&ctx, &[0x0d, 0x00, 0x00, 0x00]).unwrap(), ImageFormat::Binary);
+        let source = io::Cursor::new(&source[..]);
+        assert!(load_reader(&mut source.clone(), None, None, LoadMode::Strict).is_err());
+        assert!(load_reader(&mut source.clone(), None, Some(ImageFormat::Binary), LoadMode::Lenient).is_ok());
+    }
+
+    #[test]
+    fn detect_prefers_hexstr_for_zeros_and_ones_in_whole_hex_words() {
+        assert_eq!(ImageFormat::detect(b"0101 1000"), ImageFormat::HexStr);
+        assert_eq!(parse(b"0101 1000", ImageFormat::HexStr, LoadMode::Strict).unwrap(), vec![0x0101, 0x1000]);
+    }
+
+    fn logisim(content: &str, mode: LoadMode) -> Result<Vec<i32>, LoadError> {
+        parse(format!("v2.0 raw\n{}", content).as_bytes(), ImageFormat::LogisimRaw, mode)
+    }
+
+    #[test]
+    fn logisim_runs_repeat_a_word() {
+        assert_eq!(logisim("1 3*abcd 2\n0*5 7", LoadMode::Strict).unwrap(), vec![1, 0xabcd, 0xabcd, 0xabcd, 2, 7]);
+    }
+
+    #[test]
+    fn logisim_comments_run_to_the_end_of_the_line() {
+        assert_eq!(logisim("1 2 # 3 4\n# 5\n6#7", LoadMode::Strict).unwrap(), vec![1, 2, 6]);
+    }
+
+    #[test]
+    fn logisim_fills_exactly_the_address_space() {
+        let words = logisim("65535*0 ffff", LoadMode::Strict).unwrap();
+        assert_eq!((words.len(), words[MAX_WORDS - 1]), (MAX_WORDS, 0xffff));
+        assert_eq!(logisim("65536*0", LoadMode::Strict).unwrap().len(), MAX_WORDS);
+    }
+
+    #[test]
+    fn logisim_one_word_too_many_overflows() {
+        let err = logisim("1\n65535*0 1 2", LoadMode::Lenient).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::Overflow));
+        assert_eq!((err.line, err.column, err.token.as_str()), (3, 9, "1"));
+        let err = logisim("65537*0", LoadMode::Lenient).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::Overflow));
+        assert_eq!((err.line, err.column), (2, 1));
+    }
+
+    #[test]
+    fn logisim_huge_run_fails_without_allocating_it() {
+        let err = logisim("18446744073709551615*0", LoadMode::Lenient).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::Overflow));
+    }
+
+    #[test]
+    fn logisim_run_count_beyond_usize_is_overflow() {
+        let err = logisim("99999999999999999999*0 1234", LoadMode::Lenient).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::Overflow));
+        assert_eq!((err.line, err.column, err.token.as_str()), (2, 1, "99999999999999999999*0"));
+    }
+
+    #[test]
+    fn logisim_bad_token_lenient_or_strict() {
+        assert_eq!(logisim("1 zz 3", LoadMode::Lenient).unwrap(), vec![1, 0, 3]);
+        let err = logisim("1 zz 3", LoadMode::Strict).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::InvalidToken));
+        assert_eq!((err.line, err.column, err.token.as_str()), (2, 3, "zz"));
+    }
+
+    fn position(err: &LoadError) -> (usize, usize, &str) {
+        (err.line, err.column, err.token.as_str())
+    }
+
+    #[test]
+    fn error_positions_in_logisim_images() {
+        let err = parse(b"v3.0 raw\n1", ImageFormat::LogisimRaw, LoadMode::Lenient).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::BadHeader));
+        assert_eq!(position(&err), (1, 1, "v3.0 raw"));
+        let err = logisim("0 1\n  2  10000", LoadMode::Strict).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::OutOfRange));
+        assert_eq!(position(&err), (3, 6, "10000"));
+    }
+
+    #[test]
+    fn error_positions_in_binary_images_count_bytes() {
+        let err = parse(&[0x12, 0x34, 0x56], ImageFormat::Binary, LoadMode::Strict).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::PartialWord));
+        assert_eq!(position(&err), (1, 3, "56"));
+        let err = parse(&vec![0; MAX_WORDS * 2 + 2], ImageFormat::Binary, LoadMode::Lenient).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::Overflow));
+        assert_eq!(position(&err), (1, MAX_WORDS * 2 + 1, ""));
+    }
+
+    #[test]
+    fn error_positions_in_intel_hex_records() {
+        let image = format!("{}  :0100\n", record(0, 0x00, &[0x12]));
+        let err = parse(image.as_bytes(), ImageFormat::IntelHex, LoadMode::Strict).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::BadRecord));
+        assert_eq!(position(&err), (2, 3, ":0100"));
+        let image = ihex(&[record(0, 0x00, &[0x12]), record(0, 0x06, &[])]);
+        let err = parse(image.as_bytes(), ImageFormat::IntelHex, LoadMode::Strict).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::UnsupportedRecord));
+        assert_eq!((err.line, err.column), (2, 1));
+    }
+
+    #[test]
+    fn error_display_names_file_position_and_token() {
+        let err = logisim("1 zz", LoadMode::Strict).unwrap_err();
+        assert_eq!(err.to_string(), "<buffer>:2:3: invalid word `zz`");
+        let err = load_file(Path::new("/nonexistent/image.hex"), None, LoadMode::Strict).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::Io(_)));
+        assert_eq!(err.line, 0);
+        assert!(err.to_string().starts_with("/nonexistent/image.hex: "));
+    }
+}
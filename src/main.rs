@@ -1,23 +1,439 @@
-use std::{time::{Instant, Duration}, thread::{Thread, sleep}, os::unix::thread};
+use std::{time::{Instant, Duration}, process::exit, path::PathBuf, fs::File, io::{self, BufWriter, Write}, net::{Ipv4Addr, SocketAddr}};
 
-use pplus_emu::cpu::cpu::{CPU, is_set};
+use pplus_emu::{cpu::{cpu::{CPU, StopReason}, bus::RomWritePolicy, init::InitPattern, nvram::Nvram, watch::{WatchKind, Watchpoint}}, diff, export::DumpFormat, sourcemap::SourceMap, symbols::SymbolTable, io::{terminal::{key_name, DEFAULT_ESCAPE}, watchdog::{Watchdog, WatchdogAction}, SerialBackend, IO, DEFAULT_TELNET_ADDR}, loader::{self, ImageFormat, ImageSpec, LoadError, LoadMode}};
+
+/// memory range to write out once the CPU stops
+struct Dump {
+    format: DumpFormat,
+    path: String,
+    start: u16,
+    len: usize,
+}
+
+/// host file kept as persistent memory at `base`
+struct NvramSpec {
+    path: PathBuf,
+    base: u16,
+    len: usize,
+}
+
+struct Options {
+    images: Vec<ImageSpec>,
+    mode: LoadMode,
+    entry: Option<String>,
+    symbols: Option<PathBuf>,
+    annotated: Option<PathBuf>,
+    sources: Vec<PathBuf>,
+    rom_policy: Option<RomWritePolicy>,
+    watchdog: Option<Watchdog>,
+    dumps: Vec<Dump>,
+    watches: Vec<String>,
+    diff_on_halt: bool,
+    compare: Option<(ImageSpec, ImageSpec)>,
+    nvram: Vec<NvramSpec>,
+    nvram_interval: Option<Duration>,
+    init_mem: InitPattern,
+    init_regs: InitPattern,
+    telnet_addr: SocketAddr,
+    raw_tcp: Option<SocketAddr>,
+    unix: Option<PathBuf>,
+    observer: bool,
+    terminal: bool,
+    pty: bool,
+    escape: u8,
+    list_ports: bool,
+    log_ports: bool,
+}
+
+fn usage() -> ! {
+    eprintln!("usage: pplus-emu [options] [image[@base]]...");
+    eprintln!("  --format <fmt>          format of the images that follow: logisim16, binary, intelhex, hexstr, binstr");
+    eprintln!("  --strict                fail on any loader warning");
+    eprintln!("  --entry <addr|label>    start executing at addr instead of 0");
+    eprintln!("  --symbols <file>        customasm symbol file used for entry labels and reports");
+    eprintln!("  --annotated <file>      customasm annotated output mapping addresses to source lines");
+    eprintln!("  --source <file>         a file given to customasm, in the same order, to resolve annotated lines");
+    eprintln!("  --rom <image[@base]>    load an image and make it read-only");
+    eprintln!("  --rom-writes <policy>   what writes to read-only memory do: ignore, log (default), fault");
+    eprintln!("  --dump <fmt>:<file>[:<start>-<end>]");
+    eprintln!("                          write memory out at halt as logisim16, binary or hexdump, `-` is stdout");
+    eprintln!("  --watch <r|w|rw>:<addr>[-<end>][=<value>]");
+    eprintln!("                          stop on data reads, writes or both in a range, addresses may be labels");
+    eprintln!("  --diff-on-halt          print the memory words the run changed once the CPU stops");
+    eprintln!("  --diff <image[@base]> <image[@base]>");
+    eprintln!("                          print the words that differ between two images, e.g. earlier dumps, and exit");
+    eprintln!("  --nvram <file>@<base>:<words>");
+    eprintln!("                          keep words of memory from base in file across runs");
+    eprintln!("  --nvram-interval <ms>   also flush NVRAM this often while running, default 1000, 0 only flushes on stop");
+    eprintln!("  --init-mem <pattern>    power-on memory: zero (default), a fill word like 0xdead, or random:<seed>");
+    eprintln!("  --init-regs <pattern>   power-on registers, same patterns as --init-mem");
+    eprintln!("  --telnet <[addr:]port>  serve the serial ports over telnet here, default {}, port 0 picks a free one", DEFAULT_TELNET_ADDR);
+    eprintln!("  --raw-tcp <[addr:]port> serve the serial ports as plain bytes over TCP instead of telnet");
+    eprintln!("  --unix <path>           serve the serial ports as plain bytes on a Unix domain socket");
+    eprintln!("  --observer              let a second, read-only client watch the output");
+    eprintln!("  --terminal              use this terminal for the serial ports instead of the telnet server");
+    eprintln!("  --pty                   connect the serial ports to a new pseudo-terminal, its /dev/pts path is printed");
+    eprintln!("  --escape <key>          key returning from --terminal to the host, like ^] (default) or 0x1d");
+    eprintln!("  --ports                 print which device answers which I/O ports");
+    eprintln!("  --log-ports             warn about inp and out on ports no device answers");
+    eprintln!("  --watchdog <n>[:reset|:stop]");
+    eprintln!("                          expire after n instructions without a kick");
+    eprintln!("images load at address 0 unless given a base, program.hex is used when none are listed, `-` reads stdin");
+    exit(2);
+}
+
+/// parse `0x` prefixed hex or decimal addresses
+fn parse_addr(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// parse a `fmt:file[:start-end]` dump argument, the range is inclusive and defaults to all memory
+fn parse_dump(spec: &str) -> Option<Dump> {
+    let mut parts = spec.splitn(3, ':');
+    let format = DumpFormat::from_name(parts.next()?)?;
+    let path = parts.next().filter(|p| !p.is_empty())?.to_string();
+    let (start, len) = match parts.next() {
+        Some(range) => {
+            let (start, end) = range.split_once('-')?;
+            let (start, end) = (parse_addr(start)?, parse_addr(end)?);
+            (start, (end.checked_sub(start)? as usize) + 1)
+        }
+        None => (0, 65536),
+    };
+    Some(Dump { format, path, start, len })
+}
+
+/// parse an `image[@base]` argument
+fn parse_image(arg: &str, format: Option<ImageFormat>, read_only: bool) -> ImageSpec {
+    let (file, base) = match arg.rsplit_once('@') {
+        Some((file, base)) => (file, parse_addr(base).unwrap_or_else(|| {
+            eprintln!("[ERR] Invalid base address: {}", base);
+            exit(2);
+        })),
+        None => (arg, 0),
+    };
+    ImageSpec { file: PathBuf::from(file), base, format, read_only }
+}
+
+/// parse a `file@base:words` NVRAM argument
+fn parse_nvram(spec: &str) -> Option<NvramSpec> {
+    let (path, region) = spec.rsplit_once('@')?;
+    let (base, len) = region.split_once(':')?;
+    let (base, len) = (parse_addr(base)?, len.parse::<usize>().ok()?);
+    Some(NvramSpec { path: PathBuf::from(path), base, len }).filter(|n| !path.is_empty() && n.len > 0 && n.base as usize + n.len <= 65536)
+}
+
+/// parse `addr:port`, or a bare port on localhost
+fn parse_listen(spec: &str) -> Option<SocketAddr> {
+    match spec.parse::<u16>() {
+        Ok(port) => Some(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port)),
+        Err(_) => spec.parse().ok(),
+    }
+}
+
+/// parse a key as `^X`, a number or the character itself
+fn parse_key(spec: &str) -> Option<u8> {
+    match spec.as_bytes() {
+        [b'^', key] => Some(key.to_ascii_uppercase() ^ 0x40).filter(|k| *k < 0x20 || *k == 0x7f),
+        [key] => Some(*key),
+        _ => parse_addr(spec).and_then(|key| u8::try_from(key).ok()),
+    }
+}
+
+fn parse_watchdog(spec: &str) -> Option<Watchdog> {
+    let (timeout, action) = match spec.split_once(':') {
+        Some((timeout, "reset")) => (timeout, WatchdogAction::Reset),
+        Some((timeout, "stop")) => (timeout, WatchdogAction::Stop),
+        Some(_) => return None,
+        None => (spec, WatchdogAction::Stop),
+    };
+    match timeout.parse::<u64>() {
+        Ok(timeout) if timeout > 0 => Some(Watchdog::new(timeout, action)),
+        _ => None,
+    }
+}
+
+/// parse a `r|w|rw:addr[-end][=value]` watchpoint, addresses are numbers or labels from `symbols`
+fn parse_watch(spec: &str, symbols: &SymbolTable) -> Option<Watchpoint> {
+    let addr = |text: &str| parse_addr(text).or_else(|| symbols.lookup(text));
+    let (kind, rest) = spec.split_once(':')?;
+    let kind = match kind {
+        "r" => WatchKind::Read,
+        "w" => WatchKind::Write,
+        "rw" => WatchKind::Access,
+        _ => return None,
+    };
+    let (range, value) = match rest.split_once('=') {
+        Some((range, value)) => (range, Some(parse_addr(value)?)),
+        None => (rest, None),
+    };
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (addr(start)?, addr(end)?),
+        None => (addr(range)?, addr(range)?),
+    };
+    Some(Watchpoint { kind, start, end, value }).filter(|w| w.start <= w.end)
+}
+
+fn parse_args() -> Options {
+    let mut opts = Options { images: Vec::new(), mode: LoadMode::Lenient, entry: None, symbols: None, annotated: None, sources: Vec::new(), rom_policy: None, watchdog: None, dumps: Vec::new(), watches: Vec::new(), diff_on_halt: false, compare: None, nvram: Vec::new(), nvram_interval: Some(Duration::from_millis(1000)), init_mem: InitPattern::Zero, init_regs: InitPattern::Zero, telnet_addr: DEFAULT_TELNET_ADDR, raw_tcp: None, unix: None, observer: false, terminal: false, pty: false, escape: DEFAULT_ESCAPE, list_ports: false, log_ports: false };
+    let mut format = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--watchdog" => {
+                let spec = args.next().unwrap_or_else(|| usage());
+                opts.watchdog = Some(parse_watchdog(&spec).unwrap_or_else(|| {
+                    eprintln!("[ERR] Invalid watchdog setting: {}", spec);
+                    exit(2);
+                }));
+            }
+            "--watch" => opts.watches.push(args.next().unwrap_or_else(|| usage())),
+            "--diff-on-halt" => opts.diff_on_halt = true,
+            "--diff" => {
+                let before = args.next().unwrap_or_else(|| usage());
+                let after = args.next().unwrap_or_else(|| usage());
+                opts.compare = Some((parse_image(&before, format, false), parse_image(&after, format, false)));
+            }
+            "--nvram" => {
+                let spec = args.next().unwrap_or_else(|| usage());
+                opts.nvram.push(parse_nvram(&spec).unwrap_or_else(|| {
+                    eprintln!("[ERR] Invalid NVRAM setting: {}", spec);
+                    exit(2);
+                }));
+            }
+            "--nvram-interval" => {
+                opts.nvram_interval = match args.next().map(|ms| ms.parse::<u64>()) {
+                    Some(Ok(0)) => None,
+                    Some(Ok(ms)) => Some(Duration::from_millis(ms)),
+                    _ => usage(),
+                };
+            }
+            "--init-mem" | "--init-regs" => {
+                let spec = args.next().unwrap_or_else(|| usage());
+                let pattern = InitPattern::from_spec(&spec).unwrap_or_else(|| {
+                    eprintln!("[ERR] Invalid init pattern: {}", spec);
+                    exit(2);
+                });
+                match arg.as_str() {
+                    "--init-mem" => opts.init_mem = pattern,
+                    _ => opts.init_regs = pattern,
+                }
+            }
+            "--telnet" => {
+                let spec = args.next().unwrap_or_else(|| usage());
+                opts.telnet_addr = parse_listen(&spec).unwrap_or_else(|| {
+                    eprintln!("[ERR] Invalid telnet address: {}", spec);
+                    exit(2);
+                });
+            }
+            "--raw-tcp" => {
+                let spec = args.next().unwrap_or_else(|| usage());
+                opts.raw_tcp = Some(parse_listen(&spec).unwrap_or_else(|| {
+                    eprintln!("[ERR] Invalid raw TCP address: {}", spec);
+                    exit(2);
+                }));
+            }
+            "--unix" => opts.unix = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--observer" => opts.observer = true,
+            "--ports" => opts.list_ports = true,
+            "--log-ports" => opts.log_ports = true,
+            "--terminal" => opts.terminal = true,
+            "--pty" => opts.pty = true,
+            "--escape" => {
+                let spec = args.next().unwrap_or_else(|| usage());
+                opts.escape = parse_key(&spec).unwrap_or_else(|| {
+                    eprintln!("[ERR] Invalid escape key: {}", spec);
+                    exit(2);
+                });
+            }
+            "--format" => {
+                let name = args.next().unwrap_or_else(|| usage());
+                format = Some(ImageFormat::from_name(&name).unwrap_or_else(|| {
+                    eprintln!("[ERR] Unknown image format: {}", name);
+                    exit(2);
+                }));
+            }
+            "--strict" => opts.mode = LoadMode::Strict,
+            "--entry" => opts.entry = Some(args.next().unwrap_or_else(|| usage())),
+            "--symbols" => opts.symbols = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--annotated" => opts.annotated = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--source" => opts.sources.push(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--rom" => {
+                let image = args.next().unwrap_or_else(|| usage());
+                opts.images.push(parse_image(&image, format, true));
+            }
+            "--rom-writes" => {
+                opts.rom_policy = match args.next().as_deref() {
+                    Some("ignore") => Some(RomWritePolicy::Ignore),
+                    Some("log") => Some(RomWritePolicy::Log),
+                    Some("fault") => Some(RomWritePolicy::Fault),
+                    _ => usage(),
+                };
+            }
+            "--dump" => {
+                let spec = args.next().unwrap_or_else(|| usage());
+                opts.dumps.push(parse_dump(&spec).unwrap_or_else(|| {
+                    eprintln!("[ERR] Invalid dump setting: {}", spec);
+                    exit(2);
+                }));
+            }
+            _ if arg.starts_with('-') && arg != "-" && !arg.starts_with("-@") => usage(),
+            _ => opts.images.push(parse_image(&arg, format, false)),
+        }
+    }
+    if opts.images.is_empty() {
+        opts.images.push(parse_image("program.hex", format, false));
+    }
+    opts
+}
+
+fn write_dump(cpu: &CPU, dump: &Dump) -> io::Result<()> {
+    let mut out: Box<dyn Write> = match dump.path.as_str() {
+        "-" => Box::new(io::stdout()),
+        path => Box::new(BufWriter::new(File::create(path)?)),
+    };
+    cpu.dump_memory(&mut out, dump.format, dump.start, dump.len)?;
+    out.flush()
+}
+
+/// the whole address space as it would look with only `image` loaded
+fn image_memory(image: &ImageSpec, mode: LoadMode) -> Result<Vec<i32>, LoadError> {
+    let mut mem = vec![0; 65536];
+    for (base, words) in loader::load_images(std::slice::from_ref(image), mode)? {
+        mem[base as usize..base as usize + words.len()].copy_from_slice(&words);
+    }
+    Ok(mem)
+}
 
 fn main() {
-    let mut cpu = CPU::new();
-    cpu.load_prog();
+    let opts = parse_args();
+    let symbols = match &opts.symbols {
+        Some(file) => SymbolTable::load_file(file, opts.mode).unwrap_or_else(|err| {
+            eprintln!("[ERR] {}", err);
+            exit(1);
+        }),
+        None => SymbolTable::new(),
+    };
+    // comparing images is offline, it needs no serial backend or CPU
+    if let Some((before, after)) = &opts.compare {
+        let (before, after) = image_memory(before, opts.mode).and_then(|b| Ok((b, image_memory(after, opts.mode)?))).unwrap_or_else(|err| {
+            eprintln!("[ERR] {}", err);
+            exit(1);
+        });
+        if let Err(err) = diff::write_diff(&mut io::stdout(), &diff::diff_words(0, &before, &after), &symbols) {
+            eprintln!("[ERR] Writing diff failed: {}", err);
+        }
+        exit(0);
+    }
+    // load and check everything before the serial backend starts: `exit` skips the drop that restores the terminal
+    let mut cpu = CPU::with_io(IO::detached());
+    if let Some(policy) = opts.rom_policy {
+        cpu.mem.set_rom_policy(policy);
+    }
+    cpu.symbols = symbols;
+    if let Some(file) = &opts.annotated {
+        cpu.source_map = SourceMap::load_file(file, &opts.sources, opts.mode).unwrap_or_else(|err| {
+            eprintln!("[ERR] {}", err);
+            exit(1);
+        });
+    }
+    cpu.fill_memory(opts.init_mem);
+    cpu.set_register_init(opts.init_regs);
+    if let Err(err) = cpu.load_images(&opts.images, opts.mode) {
+        eprintln!("[ERR] {}", err);
+        exit(1);
+    }
+    for spec in &opts.nvram {
+        let nvram = Nvram::open(&spec.path, spec.len).unwrap_or_else(|err| {
+            eprintln!("[ERR] {}", err);
+            exit(1);
+        });
+        cpu.mem.map("nvram", spec.base, spec.len, Box::new(nvram));
+    }
+    if !opts.nvram.is_empty() {
+        cpu.set_flush_interval(opts.nvram_interval);
+    }
+    if let Some(entry) = &opts.entry {
+        let addr = parse_addr(entry).or_else(|| cpu.symbols.lookup(entry)).unwrap_or_else(|| {
+            eprintln!("[ERR] Invalid entry point: {}", entry);
+            exit(2);
+        });
+        cpu.set_entry(addr);
+    }
+    for spec in &opts.watches {
+        let watch = parse_watch(spec, &cpu.symbols).unwrap_or_else(|| {
+            eprintln!("[ERR] Invalid watchpoint: {}", spec);
+            exit(2);
+        });
+        cpu.watchpoints.add(watch);
+    }
+    let serial = if opts.terminal {
+        println!("[INFO] Serial ports on this terminal, press {} to return to the host", key_name(opts.escape));
+        SerialBackend::Terminal { escape: opts.escape }
+    } else if opts.pty {
+        SerialBackend::Pty
+    } else if let Some(path) = opts.unix.clone() {
+        SerialBackend::Unix { path, observer: opts.observer }
+    } else if let Some(addr) = opts.raw_tcp {
+        SerialBackend::RawTcp { addr, observer: opts.observer }
+    } else {
+        SerialBackend::Telnet { addr: opts.telnet_addr, observer: opts.observer }
+    };
+    let raw = matches!(serial, SerialBackend::RawTcp { .. });
+    let io = IO::with_serial(serial.clone()).unwrap_or_else(|err| {
+        eprintln!("[ERR] Starting {} failed: {}", serial, err);
+        exit(1);
+    });
+    if let Some(addr) = io.serial_addr() {
+        println!("[INFO] {} server listening on {}", if raw { "Raw TCP" } else { "Telnet" }, addr);
+    }
+    if let Some(path) = io.serial_path() {
+        println!("[INFO] Serial ports on {}", path.display());
+    }
+    cpu.io_space = io;
+    if let Some(watchdog) = opts.watchdog {
+        cpu.io_space.set_watchdog(watchdog);
+    }
+    cpu.io_space.ports.set_log_unmapped(opts.log_ports);
+    if opts.list_ports {
+        for (name, first, count) in cpu.io_space.ports.devices() {
+            println!("[INFO] Ports {:#04x}-{:#04x} {}", first, first as usize + count - 1, name);
+        }
+    }
+    let snapshot = opts.diff_on_halt.then(|| cpu.snapshot());
     let mut counter: u128 = 0;
     let time = Instant::now();
     let max_insts = 1_000_000;
-    loop {
-        let hlt = is_set(cpu.reg_st, 0) || counter >= max_insts;
-        if hlt {
-            break;
+    let reason = loop {
+        if let Some(reason) = cpu.stop_reason() {
+            break reason;
+        }
+        if counter >= max_insts {
+            break StopReason::InstructionLimit;
         }
         cpu.exec();
         counter += 1;
-    }
+    };
     let elapsed = time.elapsed();
+    cpu.io_space.release_terminal();
+    if let Err(err) = cpu.flush() {
+        eprintln!("[ERR] Flushing NVRAM failed: {}", err);
+    }
     std::thread::sleep(Duration::from_millis(1000));
-    print!("\n[INFO] Took {} ns to execute {} instructions, ", elapsed.as_nanos(), counter);
-    println!(" ({} kHz)", (counter*1000000) as u128/elapsed.as_nanos())
+    println!("\n[INFO] Stopped at {}: {}", cpu.describe_addr(cpu.ip()), reason.describe(|addr| cpu.describe_addr(addr)));
+    if let Some(snapshot) = &snapshot {
+        if let Err(err) = diff::write_diff(&mut io::stdout(), &cpu.diff_since(snapshot), &cpu.symbols) {
+            eprintln!("[ERR] Writing diff failed: {}", err);
+        }
+    }
+    for dump in &opts.dumps {
+        if let Err(err) = write_dump(&cpu, dump) {
+            eprintln!("[ERR] Writing memory dump to {} failed: {}", dump.path, err);
+        }
+    }
+    print!("[INFO] Took {} ns to execute {} instructions, ", elapsed.as_nanos(), counter);
+    println!(" ({} kHz)", (counter*1000000)/elapsed.as_nanos())
 }
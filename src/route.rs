@@ -0,0 +1,39 @@
+const UNMAPPED: u16 = u16::MAX;
+
+/// Which of a list of address ranges answers each address of a space
+/// - shared by the memory bus and the I/O port map to find the backend for an access
+/// - later ranges shadow earlier ones where they overlap
+pub(crate) struct RouteTable {
+    owner: Vec<u16>,
+    starts: Vec<usize>,
+}
+
+impl RouteTable {
+    /// `size` addresses with nothing mapped
+    pub(crate) fn new(size: usize) -> RouteTable {
+        RouteTable { owner: vec![UNMAPPED; size], starts: Vec::new() }
+    }
+
+    /// Recompute ownership from each range's start and length, given in mapping order
+    pub(crate) fn rebuild(&mut self, ranges: impl IntoIterator<Item = (usize, usize)>) {
+        self.owner.fill(UNMAPPED);
+        self.starts.clear();
+        for (idx, (start, len)) in ranges.into_iter().enumerate() {
+            self.owner[start..start + len].fill(idx as u16);
+            self.starts.push(start);
+        }
+    }
+
+    /// Index of the range answering `addr` and how far into that range `addr` is
+    pub(crate) fn route(&self, addr: usize) -> Option<(usize, usize)> {
+        match self.owner[addr] {
+            UNMAPPED => None,
+            idx => Some((idx as usize, addr - self.starts[idx as usize])),
+        }
+    }
+
+    /// Number of addresses in the space
+    pub(crate) fn size(&self) -> usize {
+        self.owner.len()
+    }
+}
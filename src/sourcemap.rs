@@ -0,0 +1,224 @@
+use std::{collections::BTreeMap, fmt, path::{Path, PathBuf}};
+
+use crate::loader::{Context, LoadError, LoadErrorKind, LoadMode};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Where the words at an address came from
+/// - `file` is None when the excerpt could not be found in the sources
+pub struct SourceLocation {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub text: String,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}: {}", file.display(), self.line, self.text),
+            None => write!(f, "{}", self.text),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+/// Address to source line map built from customasm `annotated` output
+pub struct SourceMap {
+    spans: BTreeMap<u16, (usize, SourceLocation)>,
+}
+
+/// one code line of the assembled sources with comments stripped
+struct SourceLine {
+    file: PathBuf,
+    line: usize,
+    code: String,
+}
+
+impl SourceLine {
+    /// the excerpt is the whole line or follows a label on it
+    fn matches(&self, text: &str) -> bool {
+        self.code == text || self.code.strip_suffix(text).is_some_and(|head| head.ends_with(' '))
+    }
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Read customasm `annotated` output and place each excerpt in `sources`
+    /// - `sources` are the files given to customasm, `#include`s inside them are followed
+    pub fn load_file(annotated: &Path, sources: &[PathBuf], mode: LoadMode) -> Result<SourceMap, LoadError> {
+        let ctx = Context { file: Some(annotated), mode };
+        let content = std::fs::read_to_string(annotated).map_err(|err| ctx.error(0, 0, "", LoadErrorKind::Io(err)))?;
+        let mut lines = Vec::new();
+        for source in sources {
+            read_source(source, &mut lines, mode)?;
+        }
+        SourceMap::parse_with(&ctx, &content, &lines)
+    }
+
+    /// Parse annotated output already in memory, without sources only the excerpts are kept
+    pub fn parse(annotated: &str, mode: LoadMode) -> Result<SourceMap, LoadError> {
+        SourceMap::parse_with(&Context { file: None, mode }, annotated, &[])
+    }
+
+    /// Lines look like ` outp | addr | data ; excerpt`, the address is in hex words
+    fn parse_with(ctx: &Context, content: &str, sources: &[SourceLine]) -> Result<SourceMap, LoadError> {
+        let mut map = SourceMap::new();
+        let mut cursor = 0;
+        for (num, line) in content.lines().enumerate().map(|(num, line)| (num + 1, line)) {
+            let mut fields = line.splitn(3, '|');
+            let (addr, rest) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(_), Some(addr), Some(rest)) => (addr.trim(), rest),
+                _ => continue,
+            };
+            let (data, excerpt) = rest.split_once(';').unwrap_or((rest, ""));
+            let digits = data.chars().filter(char::is_ascii_hexdigit).count();
+            let addr = match u16::from_str_radix(addr, 16) {
+                Ok(addr) => addr,
+                Err(_) if addr == "addr" => continue,
+                Err(_) => {
+                    ctx.warn(num, 1, line, LoadErrorKind::InvalidToken)?;
+                    continue;
+                }
+            };
+            if digits == 0 {
+                continue;
+            }
+            let text = normalize(excerpt);
+            let found = if text.is_empty() {
+                None
+            } else {
+                (cursor..sources.len()).chain(0..cursor).find(|idx| sources[*idx].matches(&text))
+            };
+            let location = match found {
+                Some(idx) => {
+                    cursor = idx + 1;
+                    SourceLocation { file: Some(sources[idx].file.clone()), line: sources[idx].line, text }
+                }
+                None => SourceLocation { file: None, line: 0, text },
+            };
+            map.spans.insert(addr, (digits.div_ceil(4), location));
+        }
+        Ok(map)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Source of the instruction or data word covering `addr`
+    pub fn lookup(&self, addr: u16) -> Option<&SourceLocation> {
+        let (start, (len, location)) = self.spans.range(..=addr).next_back()?;
+        if ((addr - start) as usize) < *len {
+            Some(location)
+        } else {
+            None
+        }
+    }
+}
+
+/// Append the code lines of `file` and everything it includes, in assembly order
+fn read_source(file: &Path, out: &mut Vec<SourceLine>, mode: LoadMode) -> Result<(), LoadError> {
+    let ctx = Context { file: Some(file), mode };
+    let content = std::fs::read_to_string(file).map_err(|err| ctx.error(0, 0, "", LoadErrorKind::Io(err)))?;
+    for (num, line) in content.lines().enumerate().map(|(num, line)| (num + 1, line)) {
+        let code = normalize(strip_comment(line));
+        if let Some(include) = code.strip_prefix("#include") {
+            let name = include.trim().trim_matches('"');
+            let path = file.parent().unwrap_or(Path::new("")).join(name);
+            read_source(&path, out, mode)?;
+        } else if !code.is_empty() {
+            out.push(SourceLine { file: file.to_path_buf(), line: num, code });
+        }
+    }
+    Ok(())
+}
+
+/// cut a `;` comment, leaving string literals alone
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..idx],
+            _ => (),
+        }
+    }
+    line
+}
+
+/// collapse runs of whitespace so tabs in the source match spaces in the excerpt
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANNOTATED: &str = " outp | addr | data (base 16)
+
+  0:0 |    0 |             ; start:
+  0:0 |    0 | 81 21       ; lsi r1, 0x12
+ 10:0 |    1 | 7f 01 01 00 ; stix r1, [r0 + 0x100]
+ 30:0 |    3 | 0d 00       ; jmpo loop
+ 40:0 |    4 |             ; put:
+ 40:0 |    4 | b1 f1       ; out r1, 0xff
+ 50:0 |    5 | 3b 78       ; #d \";x\"
+";
+
+    /// `main.asm` including `lib/put.asm`, in a fresh directory under the system temp dir
+    fn write_sources(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pplus-emu-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("main.asm"), "; demo\nstart:\n    lsi r1, 0x12 ; load\n\tstix r1,  [r0 + 0x100]\nloop: jmpo loop\n#include \"lib/put.asm\"\n").unwrap();
+        std::fs::write(dir.join("lib/put.asm"), "put:\n    out r1, 0xff\nmsg: #d \";x\" ; text\n").unwrap();
+        std::fs::write(dir.join("main.txt"), ANNOTATED).unwrap();
+        dir
+    }
+
+    fn located(map: &SourceMap, addr: u16) -> Option<(PathBuf, usize)> {
+        map.lookup(addr).map(|loc| (loc.file.clone().unwrap(), loc.line))
+    }
+
+    #[test]
+    fn excerpts_resolve_to_their_source_lines_across_includes() {
+        let dir = write_sources("sourcemap");
+        let (main, put) = (dir.join("main.asm"), dir.join("lib/put.asm"));
+        let map = SourceMap::load_file(&dir.join("main.txt"), std::slice::from_ref(&main), LoadMode::Strict).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(located(&map, 0), Some((main.clone(), 3)));
+        assert_eq!(located(&map, 1), Some((main.clone(), 4)));
+        assert_eq!(located(&map, 2), Some((main.clone(), 4)));
+        assert_eq!(located(&map, 3), Some((main.clone(), 5)));
+        assert_eq!(located(&map, 4), Some((put.clone(), 2)));
+        assert_eq!(located(&map, 5), Some((put, 3)));
+        assert_eq!(map.lookup(6), None);
+        assert_eq!(map.lookup(1).unwrap().text, "stix r1, [r0 + 0x100]");
+    }
+
+    #[test]
+    fn header_and_label_only_lines_map_no_words() {
+        let map = SourceMap::parse(ANNOTATED, LoadMode::Strict).unwrap();
+        assert_eq!(map.spans.keys().copied().collect::<Vec<u16>>(), vec![0, 1, 3, 4, 5]);
+        let loc = map.lookup(4).unwrap();
+        assert_eq!((loc.file.as_ref(), loc.text.as_str()), (None, "out r1, 0xff"));
+    }
+
+    #[test]
+    fn bad_addresses_are_warnings() {
+        let annotated = "  0:0 |   zz | 81 21 ; lsi r1, 0x12\n  2:0 |    1 | 00 00 ; halt";
+        let err = SourceMap::parse(annotated, LoadMode::Strict).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::InvalidToken));
+        assert_eq!(err.line, 1);
+        let map = SourceMap::parse(annotated, LoadMode::Lenient).unwrap();
+        assert_eq!(map.lookup(1).unwrap().text, "halt");
+    }
+
+    #[test]
+    fn comments_are_cut_outside_strings() {
+        assert_eq!(strip_comment("lsi r1, 1 ; one"), "lsi r1, 1 ");
+        assert_eq!(strip_comment("#d \"a;b\" ; text"), "#d \"a;b\" ");
+    }
+}
@@ -0,0 +1,117 @@
+use std::{collections::{BTreeMap, HashMap}, path::Path};
+
+use crate::loader::{Context, LoadError, LoadErrorKind, LoadMode};
+
+#[derive(Debug, Default)]
+/// Label addresses imported from a customasm symbol file
+/// - when several names share an address the first one read is used for display
+pub struct SymbolTable {
+    by_name: HashMap<String, u16>,
+    by_addr: BTreeMap<u16, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// Read a customasm symbol file, one `name = value` per line
+    pub fn load_file(file: &Path, mode: LoadMode) -> Result<SymbolTable, LoadError> {
+        let ctx = Context { file: Some(file), mode };
+        let content = std::fs::read_to_string(file).map_err(|err| ctx.error(0, 0, "", LoadErrorKind::Io(err)))?;
+        SymbolTable::parse_with(&ctx, &content)
+    }
+
+    /// Parse symbol file content already in memory
+    pub fn parse(content: &str, mode: LoadMode) -> Result<SymbolTable, LoadError> {
+        SymbolTable::parse_with(&Context { file: None, mode }, content)
+    }
+
+    fn parse_with(ctx: &Context, content: &str) -> Result<SymbolTable, LoadError> {
+        let mut table = SymbolTable::new();
+        for (num, line) in content.lines().enumerate().map(|(num, line)| (num + 1, line.trim())) {
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let parsed = line.split_once('=').and_then(|(name, val)| {
+                let val = val.trim();
+                let addr = match val.strip_prefix("0x") {
+                    Some(hex) => u16::from_str_radix(hex, 16).ok(),
+                    None => val.parse::<u16>().ok(),
+                };
+                Some((name.trim(), addr?)).filter(|(name, _)| !name.is_empty())
+            });
+            match parsed {
+                Some((name, addr)) => table.insert(name, addr),
+                None => ctx.warn(num, 1, line, LoadErrorKind::BadSymbol)?,
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn insert(&mut self, name: &str, addr: u16) {
+        self.by_name.insert(name.to_string(), addr);
+        self.by_addr.entry(addr).or_insert_with(|| name.to_string());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// Address of a symbol by its full name, locals are written `parent.local`
+    pub fn lookup(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).copied()
+    }
+
+    /// Closest symbol at or below `addr` and the distance from it
+    pub fn resolve(&self, addr: u16) -> Option<(&str, u16)> {
+        self.by_addr.range(..=addr).next_back().map(|(base, name)| (name.as_str(), addr - base))
+    }
+
+    /// Symbol at exactly `addr`
+    pub fn name_at(&self, addr: u16) -> Option<&str> {
+        self.by_addr.get(&addr).map(String::as_str)
+    }
+
+    /// `label+offset (0xADDR)` when a symbol covers the address, plain `0xADDR` otherwise
+    pub fn describe(&self, addr: u16) -> String {
+        match self.resolve(addr) {
+            Some((name, 0)) => format!("{} ({:#06x})", name, addr),
+            Some((name, offset)) => format!("{}+{} ({:#06x})", name, offset, addr),
+            None => format!("{:#06x}", addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYMBOLS: &str = "; customasm symbols\nstart = 0x0\nmain = 0x10\nmain.loop = 0x14\nalias = 16\n";
+
+    #[test]
+    fn describe_with_and_without_offset() {
+        let table = SymbolTable::parse(SYMBOLS, LoadMode::Strict).unwrap();
+        assert_eq!(table.describe(0x10), "main (0x0010)");
+        assert_eq!(table.describe(0x13), "main+3 (0x0013)");
+        assert_eq!(table.describe(0x15), "main.loop+1 (0x0015)");
+        assert_eq!(SymbolTable::new().describe(0x15), "0x0015");
+    }
+
+    #[test]
+    fn first_name_read_is_shown_for_an_address() {
+        let table = SymbolTable::parse(SYMBOLS, LoadMode::Strict).unwrap();
+        assert_eq!((table.lookup("alias"), table.name_at(0x10)), (Some(0x10), Some("main")));
+        assert_eq!(table.resolve(0x0f), Some(("start", 0x0f)));
+    }
+
+    #[test]
+    fn bad_symbol_points_at_its_line() {
+        let content = "start = 0x0\n\n  loop 4\n = 5\n";
+        let err = SymbolTable::parse(content, LoadMode::Strict).unwrap_err();
+        assert!(matches!(err.kind, LoadErrorKind::BadSymbol));
+        assert_eq!((err.line, err.column, err.token.as_str()), (3, 1, "loop 4"));
+        let table = SymbolTable::parse(content, LoadMode::Lenient).unwrap();
+        assert_eq!((table.lookup("start"), table.lookup("loop")), (Some(0), None));
+    }
+}